@@ -0,0 +1,375 @@
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Where rebound controls are saved, relative to the working directory.
+pub const INPUT_MAP_PATH: &str = "input_map.ron";
+// How far a stick or trigger has to move before its binding counts as held.
+const AXIS_THRESHOLD: f32 = 0.5;
+// Sticks inside this radius are treated as centred.
+const STICK_DEADZONE: f32 = 0.2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum PlayerAction {
+    Thrust,
+    StrafeLeft,
+    StrafeRight,
+    Fire,
+    Blade,
+    Weapon1,
+    Weapon2,
+    Weapon3,
+    Weapon4,
+    NextWeapon,
+    PreviousWeapon,
+    Pause,
+    ToggleFps,
+}
+
+impl PlayerAction {
+    /// Every action, in the order the settings screen lists them.
+    pub const ALL: [PlayerAction; 13] = [
+        PlayerAction::Thrust,
+        PlayerAction::StrafeLeft,
+        PlayerAction::StrafeRight,
+        PlayerAction::Fire,
+        PlayerAction::Blade,
+        PlayerAction::Weapon1,
+        PlayerAction::Weapon2,
+        PlayerAction::Weapon3,
+        PlayerAction::Weapon4,
+        PlayerAction::NextWeapon,
+        PlayerAction::PreviousWeapon,
+        PlayerAction::Pause,
+        PlayerAction::ToggleFps,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PlayerAction::Thrust => "Thrust",
+            PlayerAction::StrafeLeft => "Strafe Left",
+            PlayerAction::StrafeRight => "Strafe Right",
+            PlayerAction::Fire => "Fire",
+            PlayerAction::Blade => "Blade",
+            PlayerAction::Weapon1 => "Weapon 1",
+            PlayerAction::Weapon2 => "Weapon 2",
+            PlayerAction::Weapon3 => "Weapon 3",
+            PlayerAction::Weapon4 => "Weapon 4",
+            PlayerAction::NextWeapon => "Next Weapon",
+            PlayerAction::PreviousWeapon => "Previous Weapon",
+            PlayerAction::Pause => "Pause",
+            PlayerAction::ToggleFps => "Toggle FPS",
+        }
+    }
+}
+
+/// A physical input that can trigger an action.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum InputBinding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    GamepadButton(GamepadButtonType),
+    /// Held while the axis is pushed past AXIS_THRESHOLD in the given direction.
+    GamepadAxis {
+        axis: GamepadAxisType,
+        positive: bool,
+    },
+}
+
+impl InputBinding {
+    pub fn label(&self) -> String {
+        match self {
+            InputBinding::Key(key) => format!("{:?}", key),
+            InputBinding::Mouse(button) => format!("Mouse {:?}", button),
+            InputBinding::GamepadButton(button) => format!("Pad {:?}", button),
+            InputBinding::GamepadAxis { axis, positive } => {
+                format!("Pad {:?}{}", axis, if *positive { "+" } else { "-" })
+            }
+        }
+    }
+
+    fn device(&self) -> InputDevice {
+        match self {
+            InputBinding::Key(_) => InputDevice::Keyboard,
+            InputBinding::Mouse(_) => InputDevice::Mouse,
+            InputBinding::GamepadButton(_) | InputBinding::GamepadAxis { .. } => {
+                InputDevice::Gamepad
+            }
+        }
+    }
+}
+
+// Each action keeps at most one binding per device.
+#[derive(PartialEq)]
+enum InputDevice {
+    Keyboard,
+    Mouse,
+    Gamepad,
+}
+
+/// Which inputs trigger each PlayerAction, loaded from and saved to INPUT_MAP_PATH.
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+pub struct InputMap {
+    bindings: BTreeMap<PlayerAction, Vec<InputBinding>>,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        use InputBinding::{GamepadButton as Pad, Key, Mouse};
+        let bindings = [
+            (
+                PlayerAction::Thrust,
+                vec![Key(KeyCode::Space), Pad(GamepadButtonType::South)],
+            ),
+            (
+                PlayerAction::StrafeLeft,
+                vec![Key(KeyCode::A), Pad(GamepadButtonType::LeftTrigger)],
+            ),
+            (
+                PlayerAction::StrafeRight,
+                vec![Key(KeyCode::F), Pad(GamepadButtonType::RightTrigger)],
+            ),
+            (
+                PlayerAction::Fire,
+                vec![
+                    Key(KeyCode::S),
+                    Mouse(MouseButton::Left),
+                    Pad(GamepadButtonType::RightTrigger2),
+                ],
+            ),
+            (
+                PlayerAction::Blade,
+                vec![Key(KeyCode::B), Pad(GamepadButtonType::West)],
+            ),
+            (PlayerAction::Weapon1, vec![Key(KeyCode::Key1)]),
+            (PlayerAction::Weapon2, vec![Key(KeyCode::Key2)]),
+            (PlayerAction::Weapon3, vec![Key(KeyCode::Key3)]),
+            (PlayerAction::Weapon4, vec![Key(KeyCode::Key4)]),
+            (
+                PlayerAction::NextWeapon,
+                vec![Pad(GamepadButtonType::DPadRight)],
+            ),
+            (
+                PlayerAction::PreviousWeapon,
+                vec![Pad(GamepadButtonType::DPadLeft)],
+            ),
+            (
+                PlayerAction::Pause,
+                vec![Key(KeyCode::Q), Pad(GamepadButtonType::Start)],
+            ),
+            (
+                PlayerAction::ToggleFps,
+                vec![Key(KeyCode::F12), Pad(GamepadButtonType::Select)],
+            ),
+        ];
+        InputMap {
+            bindings: bindings.into_iter().collect(),
+        }
+    }
+}
+
+impl InputMap {
+    /// Reads the saved map, falling back to the defaults if there isn't a usable one.
+    pub fn load() -> Self {
+        let saved = match std::fs::read_to_string(INPUT_MAP_PATH) {
+            Ok(contents) => contents,
+            Err(_) => return InputMap::default(),
+        };
+        match ron::de::from_str::<InputMap>(&saved) {
+            Ok(mut input_map) => {
+                // Actions added since the file was written keep their defaults.
+                for (action, bindings) in InputMap::default().bindings {
+                    input_map.bindings.entry(action).or_insert(bindings);
+                }
+                input_map
+            }
+            Err(error) => {
+                warn!("Could not parse {}: {}", INPUT_MAP_PATH, error);
+                InputMap::default()
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        let result = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|error| error.to_string())
+            .and_then(|contents| {
+                std::fs::write(INPUT_MAP_PATH, contents).map_err(|error| error.to_string())
+            });
+        if let Err(error) = result {
+            warn!("Could not save {}: {}", INPUT_MAP_PATH, error);
+        }
+    }
+
+    pub fn bindings(&self, action: PlayerAction) -> &[InputBinding] {
+        self.bindings
+            .get(&action)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Binds `binding` to `action`, replacing the action's other bindings for the same
+    /// device and taking `binding` away from any other action.
+    pub fn rebind(&mut self, action: PlayerAction, binding: InputBinding) {
+        for bindings in self.bindings.values_mut() {
+            bindings.retain(|other| *other != binding);
+        }
+        let bindings = self.bindings.entry(action).or_default();
+        bindings.retain(|other| other.device() != binding.device());
+        bindings.push(binding);
+    }
+}
+
+/// Which actions are held this frame, rebuilt from the InputMap before Update runs.
+#[derive(Resource, Default, Debug)]
+pub struct ActionState {
+    pressed: HashSet<PlayerAction>,
+    just_pressed: HashSet<PlayerAction>,
+    move_stick: Vec2,
+    aim_stick: Vec2,
+    // Set by touching a stick, cleared by moving or clicking the mouse.
+    twin_stick: bool,
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: PlayerAction) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: PlayerAction) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    /// Left stick, zero while centred.
+    pub fn move_stick(&self) -> Vec2 {
+        self.move_stick
+    }
+
+    /// Where to aim from `origin`: the right stick in twin-stick mode, the cursor otherwise.
+    /// None when neither points anywhere, in which case keep the current facing.
+    pub fn aim_direction(&self, origin: Vec2, cursor: Option<Vec2>) -> Option<Vec2> {
+        let direction = if self.twin_stick {
+            self.aim_stick
+        } else {
+            cursor? - origin
+        };
+        Some(direction.normalize_or_zero()).filter(|direction| *direction != Vec2::ZERO)
+    }
+}
+
+pub struct ControlsPlugin;
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(InputMap::load())
+            .init_resource::<ActionState>()
+            .add_systems(PreUpdate, update_action_state.after(InputSystem));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_action_state(
+    mut action_state: ResMut<ActionState>,
+    input_map: Res<InputMap>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    mut cursor_moved: EventReader<CursorMoved>,
+) {
+    let held = |binding: &InputBinding| match *binding {
+        InputBinding::Key(key) => keyboard_input.pressed(key),
+        InputBinding::Mouse(button) => mouse_input.pressed(button),
+        InputBinding::GamepadButton(button) => gamepads
+            .iter()
+            .any(|gamepad| gamepad_buttons.pressed(GamepadButton::new(gamepad, button))),
+        InputBinding::GamepadAxis { axis, positive } => gamepads.iter().any(|gamepad| {
+            let value = gamepad_axes
+                .get(GamepadAxis::new(gamepad, axis))
+                .unwrap_or(0.);
+            if positive {
+                value > AXIS_THRESHOLD
+            } else {
+                value < -AXIS_THRESHOLD
+            }
+        }),
+    };
+    let mut pressed: HashSet<PlayerAction> = PlayerAction::ALL
+        .into_iter()
+        .filter(|action| input_map.bindings(*action).iter().any(held))
+        .collect();
+
+    let stick = |x: GamepadAxisType, y: GamepadAxisType| {
+        gamepads
+            .iter()
+            .map(|gamepad| {
+                Vec2::new(
+                    gamepad_axes.get(GamepadAxis::new(gamepad, x)).unwrap_or(0.),
+                    gamepad_axes.get(GamepadAxis::new(gamepad, y)).unwrap_or(0.),
+                )
+            })
+            .find(|stick| stick.length() > STICK_DEADZONE)
+            .map(|stick| stick.clamp_length_max(1.))
+            .unwrap_or(Vec2::ZERO)
+    };
+    action_state.move_stick = stick(GamepadAxisType::LeftStickX, GamepadAxisType::LeftStickY);
+    action_state.aim_stick = stick(GamepadAxisType::RightStickX, GamepadAxisType::RightStickY);
+    if action_state.move_stick != Vec2::ZERO || action_state.aim_stick != Vec2::ZERO {
+        action_state.twin_stick = true;
+    }
+    if cursor_moved.read().count() > 0 || mouse_input.get_just_pressed().next().is_some() {
+        action_state.twin_stick = false;
+    }
+    // Pushing the right stick all the way out fires along it.
+    if action_state.twin_stick && action_state.aim_stick.length() > AXIS_THRESHOLD {
+        pressed.insert(PlayerAction::Fire);
+    }
+    action_state.just_pressed = pressed.difference(&action_state.pressed).copied().collect();
+    action_state.pressed = pressed;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebind_replaces_the_same_device_only() {
+        let mut input_map = InputMap::default();
+        input_map.rebind(PlayerAction::Thrust, InputBinding::Key(KeyCode::W));
+        assert_eq!(
+            input_map.bindings(PlayerAction::Thrust),
+            &[
+                InputBinding::GamepadButton(GamepadButtonType::South),
+                InputBinding::Key(KeyCode::W),
+            ]
+        );
+    }
+
+    #[test]
+    fn rebinding_fire_to_a_key_keeps_the_mouse() {
+        let mut input_map = InputMap::default();
+        input_map.rebind(PlayerAction::Fire, InputBinding::Key(KeyCode::J));
+        assert_eq!(
+            input_map.bindings(PlayerAction::Fire),
+            &[
+                InputBinding::Mouse(MouseButton::Left),
+                InputBinding::GamepadButton(GamepadButtonType::RightTrigger2),
+                InputBinding::Key(KeyCode::J),
+            ]
+        );
+    }
+
+    #[test]
+    fn rebind_takes_the_binding_from_other_actions() {
+        let mut input_map = InputMap::default();
+        input_map.rebind(PlayerAction::Thrust, InputBinding::Key(KeyCode::S));
+        assert!(!input_map
+            .bindings(PlayerAction::Fire)
+            .contains(&InputBinding::Key(KeyCode::S)));
+        assert!(input_map
+            .bindings(PlayerAction::Thrust)
+            .contains(&InputBinding::Key(KeyCode::S)));
+    }
+}
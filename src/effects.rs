@@ -0,0 +1,185 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_hanabi::prelude::*;
+
+// Emitters kept per effect, so several bursts of one kind can play in the same frame.
+const EMITTERS_PER_EFFECT: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EffectKind {
+    Explosion,
+    Spark,
+    ShardPickup,
+}
+
+/// Plays a one shot particle burst of `kind` at `translation`.
+#[derive(Event)]
+pub struct SpawnEffect {
+    pub kind: EffectKind,
+    pub translation: Vec3,
+}
+
+struct EffectEmitters {
+    entities: Vec<Entity>,
+    next: usize,
+}
+
+// Emitters built at startup, they get moved to wherever an effect is wanted and restarted.
+#[derive(Resource)]
+struct Effects(HashMap<EffectKind, EffectEmitters>);
+
+pub struct EffectsPlugin;
+impl Plugin for EffectsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SpawnEffect>()
+            .add_systems(Startup, setup_effects)
+            .add_systems(Update, play_effects.run_if(on_event::<SpawnEffect>()));
+    }
+}
+
+// A single burst flying outwards from the emitter, it waits for a reset to play.
+fn burst_effect(
+    name: &str,
+    count: f32,
+    speed: (f32, f32),
+    lifetime: f32,
+    color_gradient: Gradient<Vec4>,
+    size_gradient: Gradient<Vec2>,
+) -> EffectAsset {
+    let writer = ExprWriter::new();
+
+    // Give a bit of variation by randomizing the age per particle. This will
+    // control the starting color and starting size of particles.
+    let age = writer.lit(0.).uniform(writer.lit(0.2)).expr();
+    let init_age = SetAttributeModifier::new(Attribute::AGE, age);
+
+    let lifetime = writer
+        .lit(lifetime * 0.8)
+        .uniform(writer.lit(lifetime * 1.2))
+        .expr();
+    let init_lifetime = SetAttributeModifier::new(Attribute::LIFETIME, lifetime);
+
+    // Add drag to make particles slow down a bit after the initial burst
+    let drag = writer.lit(5.).expr();
+    let update_drag = LinearDragModifier::new(drag);
+
+    let init_pos = SetPositionCircleModifier {
+        center: writer.lit(Vec3::ZERO).expr(),
+        axis: writer.lit(Vec3::Z).expr(),
+        radius: writer.lit(2.).expr(),
+        dimension: ShapeDimension::Volume,
+    };
+    let init_vel = SetVelocityCircleModifier {
+        center: writer.lit(Vec3::ZERO).expr(),
+        axis: writer.lit(Vec3::Z).expr(),
+        speed: writer.lit(speed.0).uniform(writer.lit(speed.1)).expr(),
+    };
+
+    EffectAsset::new(4096, Spawner::once(count.into(), false), writer.finish())
+        .with_name(name)
+        .init(init_pos)
+        .init(init_vel)
+        .init(init_age)
+        .init(init_lifetime)
+        .update(update_drag)
+        .render(ColorOverLifetimeModifier {
+            gradient: color_gradient,
+        })
+        .render(SizeOverLifetimeModifier {
+            gradient: size_gradient,
+            screen_space_size: false,
+        })
+}
+
+fn setup_effects(mut commands: Commands, mut assets: ResMut<Assets<EffectAsset>>) {
+    let mut explosion_color = Gradient::new();
+    explosion_color.add_key(0.0, Vec4::new(4.0, 4.0, 4.0, 1.0));
+    explosion_color.add_key(0.1, Vec4::new(4.0, 4.0, 0.0, 1.0));
+    explosion_color.add_key(0.9, Vec4::new(4.0, 0.0, 0.0, 1.0));
+    explosion_color.add_key(1.0, Vec4::new(4.0, 0.0, 0.0, 0.0));
+    let mut explosion_size = Gradient::new();
+    explosion_size.add_key(0.0, Vec2::splat(4.));
+    explosion_size.add_key(0.3, Vec2::splat(4.));
+    explosion_size.add_key(1.0, Vec2::splat(0.));
+
+    let mut spark_color = Gradient::new();
+    spark_color.add_key(0.0, Vec4::new(4.0, 4.0, 2.0, 1.0));
+    spark_color.add_key(1.0, Vec4::new(4.0, 2.0, 0.0, 0.0));
+    let mut spark_size = Gradient::new();
+    spark_size.add_key(0.0, Vec2::splat(2.));
+    spark_size.add_key(1.0, Vec2::splat(0.));
+
+    let mut pickup_color = Gradient::new();
+    pickup_color.add_key(0.0, Vec4::new(1.0, 3.0, 4.0, 1.0));
+    pickup_color.add_key(1.0, Vec4::new(0.0, 1.0, 4.0, 0.0));
+    let mut pickup_size = Gradient::new();
+    pickup_size.add_key(0.0, Vec2::splat(3.));
+    pickup_size.add_key(1.0, Vec2::splat(0.));
+
+    let effects = [
+        (
+            EffectKind::Explosion,
+            burst_effect(
+                "Explosion",
+                300.,
+                (60., 220.),
+                0.9,
+                explosion_color,
+                explosion_size,
+            ),
+        ),
+        (
+            EffectKind::Spark,
+            burst_effect("Spark", 16., (80., 200.), 0.25, spark_color, spark_size),
+        ),
+        (
+            EffectKind::ShardPickup,
+            burst_effect(
+                "ShardPickup",
+                30.,
+                (20., 80.),
+                0.4,
+                pickup_color,
+                pickup_size,
+            ),
+        ),
+    ];
+
+    let mut emitters = HashMap::new();
+    for (kind, effect) in effects {
+        let handle = assets.add(effect);
+        let entities = (0..EMITTERS_PER_EFFECT)
+            .map(|_| {
+                commands
+                    .spawn(ParticleEffectBundle {
+                        effect: ParticleEffect::new(handle.clone()).with_z_layer_2d(Some(10.)),
+                        ..default()
+                    })
+                    .insert(Name::new(format!("{:?}Emitter", kind)))
+                    .id()
+            })
+            .collect();
+        emitters.insert(kind, EffectEmitters { entities, next: 0 });
+    }
+    commands.insert_resource(Effects(emitters));
+}
+
+fn play_effects(
+    mut events: EventReader<SpawnEffect>,
+    mut effects: ResMut<Effects>,
+    mut emitters: Query<(&mut Transform, &mut EffectSpawner)>,
+) {
+    for event in events.read() {
+        let Some(emitter) = effects.0.get_mut(&event.kind) else {
+            continue;
+        };
+        // Round robin, the oldest burst of this kind is the one that gets cut short.
+        let entity = emitter.entities[emitter.next];
+        emitter.next = (emitter.next + 1) % emitter.entities.len();
+        let Ok((mut transform, mut spawner)) = emitters.get_mut(entity) else {
+            continue;
+        };
+        transform.translation = event.translation;
+        spawner.reset();
+    }
+}
@@ -1,7 +1,10 @@
 use std::ops::{Deref, DerefMut};
 
-use crate::guns::{Blade, BladeEvent};
+use crate::controls::{InputBinding, InputMap, PlayerAction};
+use crate::guns::{Weapon, WeaponSlots};
+use crate::mobs::KillCount;
 use crate::player::{LevelUpEvent, Player};
+use crate::upgrades::{draw_upgrades, Upgrade, UpgradeChosen};
 use bevy::a11y::accesskit::TextAlign;
 use bevy::app::AppExit;
 use bevy::prelude::*;
@@ -9,6 +12,7 @@ use bevy::reflect::Map;
 use bevy::time::Stopwatch;
 use bevy::utils::hashbrown::HashMap;
 use bevy_rapier2d::prelude::*;
+use rand::thread_rng;
 
 const NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
 const HOVERED_BUTTON: Color = Color::rgb(0.25, 0.25, 0.25);
@@ -23,10 +27,12 @@ pub struct GameRuntime(pub Stopwatch);
 #[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Hash, States)]
 pub enum GameState {
     Paused,
-    #[default]
     Playing,
+    #[default]
     StartMenu,
     LevelingUp,
+    GameOver,
+    Settings,
 }
 
 #[derive(Component)]
@@ -35,22 +41,85 @@ enum PauseButtons {
     Exit,
 }
 #[derive(Component)]
+enum StartMenuButtons {
+    NewGame,
+    Settings,
+    Quit,
+}
+#[derive(Component)]
+enum SettingsButtons {
+    ResetDefaults,
+    Back,
+}
+// A row on the settings screen, pressing it waits for the action's new input.
+#[derive(Component)]
+struct RebindButton(PlayerAction);
+// The action waiting on an input, `armed` once the click that picked it has been let go.
+#[derive(Resource, Default)]
+struct Rebinding {
+    action: Option<PlayerAction>,
+    armed: bool,
+}
+#[derive(Component)]
+enum GameOverButtons {
+    Restart,
+    Exit,
+}
+#[derive(Component)]
 enum LevelUpButtons {
     OptionOne,
     OptionTwo,
     OptionThree,
 }
+// Buttons whose Interaction changed since the last run.
+type ChangedButton = (Changed<Interaction>, With<Button>);
 #[derive(Component)]
 pub struct SelectedOption;
+#[derive(Component)]
+struct HudRoot;
+// Root of a full screen menu, despawned along with its children when the menu closes.
+#[derive(Component)]
+struct MenuRoot;
+// UI needs a camera to render, the game camera is only spawned once Playing.
+#[derive(Component)]
+struct MenuCamera;
 
 pub struct GameInterfacePlugin;
 impl Plugin for GameInterfacePlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(VelocityStorage(HashMap::new()))
-            .add_systems(PostStartup, setup_hud.run_if(in_state(GameState::Playing)))
+            .init_resource::<Rebinding>()
+            .add_systems(
+                OnEnter(GameState::Playing),
+                setup_hud.run_if(not(any_with_component::<HudRoot>())),
+            )
+            .add_systems(OnEnter(GameState::StartMenu), setup_start_menu)
+            .add_systems(OnExit(GameState::StartMenu), despawn_menu)
+            // The settings screen shares the start menu's camera.
+            .add_systems(OnEnter(GameState::Playing), despawn_menu_camera)
+            .add_systems(OnEnter(GameState::Settings), setup_settings_menu)
+            .add_systems(
+                OnExit(GameState::Settings),
+                (despawn_menu, cancel_rebinding),
+            )
             .add_systems(
                 FixedUpdate,
-                update_health_system.run_if(in_state(GameState::Playing)),
+                (button_system, apply_settings_button_system).run_if(in_state(GameState::Settings)),
+            )
+            .add_systems(
+                Update,
+                (listen_for_rebinding, update_binding_labels)
+                    .chain()
+                    .run_if(in_state(GameState::Settings)),
+            )
+            .add_systems(
+                FixedUpdate,
+                (button_system, apply_start_menu_button_system)
+                    .run_if(in_state(GameState::StartMenu)),
+            )
+            .add_systems(
+                FixedUpdate,
+                (update_health_system, update_weapon_hud).run_if(in_state(GameState::Playing)),
             )
             .add_systems(Update, save_velocity_system)
             .add_systems(OnEnter(GameState::Paused), setup_pause_menu)
@@ -65,12 +134,20 @@ impl Plugin for GameInterfacePlugin {
             .add_systems(
                 FixedUpdate,
                 (button_system, apply_pause_menu_button_system).run_if(in_state(GameState::Paused)),
+            )
+            .add_systems(OnEnter(GameState::GameOver), setup_game_over_menu)
+            .add_systems(OnExit(GameState::GameOver), (despawn_menu, reset_runtime))
+            .add_systems(
+                FixedUpdate,
+                (button_system, apply_game_over_button_system)
+                    .run_if(in_state(GameState::GameOver)),
             );
     }
 }
 
-fn setup_hud(mut commands: Commands, player: Query<&Player>, asset_server: Res<AssetServer>) {
-    let player_data = player.single();
+fn setup_hud(mut commands: Commands, asset_server: Res<AssetServer>) {
+    // The player is spawned in the same schedule, so start from a fresh ship's stats.
+    let player_data = Player::default();
     // Spawn the health bars.
     commands
         .spawn(ImageBundle {
@@ -83,6 +160,7 @@ fn setup_hud(mut commands: Commands, player: Query<&Player>, asset_server: Res<A
             image: asset_server.load("healthbar.png").into(),
             ..default()
         })
+        .insert(HudRoot)
         .with_children(|bar| {
             // The colored inner health bar
 
@@ -176,6 +254,63 @@ fn setup_hud(mut commands: Commands, player: Query<&Player>, asset_server: Res<A
             ))
             .insert(Name::new("LevelText"));
         });
+
+    // Weapon slots, filled in by update_weapon_hud once the player is armed.
+    commands
+        .spawn((
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font_size: 25.0,
+                    ..default()
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                bottom: Val::Percent(1.),
+                right: Val::Percent(1.),
+                ..default()
+            }),
+            Label,
+        ))
+        .insert(HudRoot)
+        .insert(Name::new("WeaponText"));
+}
+
+fn update_weapon_hud(
+    slots_query: Query<&WeaponSlots>,
+    weapon_query: Query<&Weapon>,
+    mut text_query: Query<(&Name, &mut Text)>,
+) {
+    let Ok(slots) = slots_query.get_single() else {
+        return;
+    };
+    for (ui_name, mut text) in text_query.iter_mut() {
+        if ui_name.as_str() != "WeaponText" {
+            continue;
+        }
+        text.sections = slots
+            .weapons
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, weapon)| {
+                let weapon = weapon_query.get(*weapon).ok()?;
+                let color = if slot == slots.active {
+                    Color::YELLOW
+                } else {
+                    Color::GRAY
+                };
+                Some(TextSection::new(
+                    format!("{} {}   ", slot + 1, weapon.kind.name()),
+                    TextStyle {
+                        font_size: 25.0,
+                        color,
+                        ..default()
+                    },
+                ))
+            })
+            .collect();
+    }
 }
 
 fn update_health_system(
@@ -235,7 +370,7 @@ fn save_velocity_system(
                         velocity_storage.0.get(&entity_id).unwrap_or(default_vel);
                     *velocity = *unpaused_velocity;
                 }
-                GameState::Paused | GameState::LevelingUp => {
+                GameState::Paused | GameState::LevelingUp | GameState::GameOver => {
                     // Save old force
                     velocity_storage.0.insert(entity_id, *velocity);
 
@@ -272,6 +407,7 @@ fn setup_pause_menu(mut commands: Commands) {
             },
             ..Default::default()
         },))
+        .insert(MenuRoot)
         .insert(Name::new("PauseMenuRoot"))
         .id();
 
@@ -354,18 +490,16 @@ fn setup_pause_menu(mut commands: Commands) {
     ]);
 }
 
-fn despawn_menu(mut commands: Commands, query: Query<(Entity, &Name)>) {
-    for (entity, name) in query.iter() {
-        if name.as_str() == "PauseMenuRoot" {
-            commands.entity(entity).despawn_recursive()
-        }
+fn despawn_menu(mut commands: Commands, query: Query<Entity, With<MenuRoot>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive()
     }
 }
 
 fn button_system(
     mut interaction_query: Query<
         (&Interaction, &mut BackgroundColor, Option<&SelectedOption>),
-        (Changed<Interaction>, With<Button>),
+        ChangedButton,
     >,
 ) {
     for (interaction, mut color, selected) in interaction_query.iter_mut() {
@@ -381,7 +515,7 @@ fn button_system(
 fn apply_pause_menu_button_system(
     mut interaction_query: Query<
         (&Interaction, &mut BackgroundColor, &PauseButtons),
-        (Changed<Interaction>, With<Button>),
+        ChangedButton,
     >,
     mut gamestate: ResMut<NextState<GameState>>,
     mut event_writer: EventWriter<AppExit>,
@@ -421,7 +555,8 @@ fn setup_levelup_menu(mut commands: Commands) {
             },
             ..Default::default()
         },))
-        .insert(Name::new("PauseMenuRoot"))
+        .insert(MenuRoot)
+        .insert(Name::new("LevelUpMenuRoot"))
         .id();
 
     let box_and_title = commands
@@ -461,58 +596,484 @@ fn setup_levelup_menu(mut commands: Commands) {
         ..default()
     };
 
-    // TODO: randomize how the values into common, epic, legendary variants.
-    let blade_powerup = Blade {
-        slash_dmg: 1.2,
-        bleed: 1.2,
-        length: 2.,
-        swing_speed: 1.2,
-        pierce: 1.0,
-    };
-    // let powerups = vec![blade_powerup];
-
-    // commands.entity(root).push_children(&[box_and_title]);
-    for i in 0..3 {
-        // TODO: randomly select powerups to show.
-        let powerup = blade_powerup.clone();
+    for (i, upgrade) in draw_upgrades(&mut thread_rng(), 3).into_iter().enumerate() {
         let button = commands
             .spawn(button.clone())
             .with_children(|parent| {
                 parent.spawn(TextBundle::from_section(
-                    format!("{:?}", powerup),
-                    button_text_style.clone(),
+                    upgrade.description(),
+                    TextStyle {
+                        color: upgrade.rarity.color(),
+                        ..button_text_style.clone()
+                    },
                 ));
             })
-            .insert(blade_powerup.clone())
+            .insert(upgrade)
             .insert(Interaction::default())
             .insert(Button)
             .insert(Name::new(format!("LevelUpOption_{}", i)))
             .id();
 
-        commands.entity(button).push_children(&[]);
         commands.entity(root).push_children(&[button]);
     }
 }
 
 fn apply_levelup_menu_button_system(
+    interaction_query: Query<(&Interaction, &Upgrade), ChangedButton>,
+    mut gamestate: ResMut<NextState<GameState>>,
+    mut event_writer: EventWriter<UpgradeChosen>,
+) {
+    for (interaction, upgrade) in interaction_query.iter() {
+        if *interaction == Interaction::Pressed {
+            event_writer.send(UpgradeChosen(*upgrade));
+            gamestate.set(GameState::Playing);
+        }
+    }
+}
+
+fn setup_game_over_menu(
     mut commands: Commands,
-    mut interaction_query: Query<
-        (Entity, &Interaction, &Name),
-        (Changed<Interaction>, With<Button>),
+    stopwatch: Res<GameRuntime>,
+    player: Query<&Player>,
+    kills: Res<KillCount>,
+) {
+    let level = player.get_single().map(|player| player.level).unwrap_or(1);
+    let root = commands
+        .spawn((NodeBundle {
+            background_color: BackgroundColor(Color::MAROON.with_a(0.9)),
+            // make it "always on top" by setting the Z index to maximum
+            // we want it to be displayed over all other UI
+            z_index: ZIndex::Global(i32::MAX),
+            style: Style {
+                position_type: PositionType::Absolute,
+                right: Val::Percent(35.),
+                top: Val::Percent(20.),
+                bottom: Val::Auto,
+                left: Val::Percent(35.),
+                padding: UiRect::all(Val::Px(4.0)),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        },))
+        .insert(MenuRoot)
+        .insert(Name::new("GameOverMenuRoot"))
+        .id();
+
+    let title = commands
+        .spawn(TextBundle::from_section(
+            "Game Over",
+            TextStyle {
+                font_size: 48.0,
+                color: Color::WHITE,
+                ..default()
+            },
+        ))
+        .id();
+
+    let stats_style = TextStyle {
+        font_size: 24.0,
+        color: Color::WHITE,
+        ..default()
+    };
+    let stats = commands
+        .spawn(TextBundle::from_sections([
+            TextSection::new(
+                format!("Survived: {:.0}s\n", stopwatch.0.elapsed_secs()),
+                stats_style.clone(),
+            ),
+            TextSection::new(format!("Level: {}\n", level), stats_style.clone()),
+            TextSection::new(format!("Kills: {}", kills.0), stats_style.clone()),
+        ]))
+        .id();
+
+    let button_style = Style {
+        width: Val::Px(250.0),
+        height: Val::Px(65.0),
+        margin: UiRect::all(Val::Px(20.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let button_text_style = TextStyle {
+        font_size: 24.0,
+        ..default()
+    };
+    let button = ButtonBundle {
+        style: button_style.clone(),
+        background_color: NORMAL_BUTTON.into(),
+        ..default()
+    };
+
+    let restart_button = commands
+        .spawn(button.clone())
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Restart",
+                button_text_style.clone(),
+            ));
+        })
+        .insert(GameOverButtons::Restart)
+        .id();
+
+    let exit_button = commands
+        .spawn(button.clone())
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section("Exit", button_text_style.clone()));
+        })
+        .insert(GameOverButtons::Exit)
+        .id();
+
+    commands
+        .entity(root)
+        .push_children(&[title, stats, restart_button, exit_button]);
+}
+
+fn apply_game_over_button_system(
+    interaction_query: Query<(&Interaction, &GameOverButtons), ChangedButton>,
+    mut gamestate: ResMut<NextState<GameState>>,
+    mut event_writer: EventWriter<AppExit>,
+) {
+    for (interaction, selected) in interaction_query.iter() {
+        match (*interaction, selected) {
+            (Interaction::Pressed, GameOverButtons::Restart) => gamestate.set(GameState::Playing),
+            (Interaction::Pressed, GameOverButtons::Exit) => event_writer.send(AppExit),
+            (_, _) => (),
+        };
+    }
+}
+
+// Leaving the death screen always starts a fresh run.
+fn reset_runtime(
+    mut stopwatch: ResMut<GameRuntime>,
+    mut velocity_storage: ResMut<VelocityStorage>,
+) {
+    stopwatch.0.reset();
+    stopwatch.0.unpause();
+    velocity_storage.0.clear();
+}
+
+fn setup_start_menu(mut commands: Commands, cameras: Query<(), With<MenuCamera>>) {
+    // Coming back from the settings screen the camera is still around.
+    if cameras.is_empty() {
+        commands.spawn((Camera2dBundle::default(), MenuCamera));
+    }
+
+    let root = commands
+        .spawn((NodeBundle {
+            background_color: BackgroundColor(Color::MIDNIGHT_BLUE.with_a(0.9)),
+            // make it "always on top" by setting the Z index to maximum
+            // we want it to be displayed over all other UI
+            z_index: ZIndex::Global(i32::MAX),
+            style: Style {
+                position_type: PositionType::Absolute,
+                right: Val::Percent(35.),
+                top: Val::Percent(20.),
+                bottom: Val::Auto,
+                left: Val::Percent(35.),
+                padding: UiRect::all(Val::Px(4.0)),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        },))
+        .insert(MenuRoot)
+        .insert(Name::new("StartMenuRoot"))
+        .id();
+
+    let title = commands
+        .spawn(TextBundle::from_section(
+            "Bevy Asteroid",
+            TextStyle {
+                font_size: 48.0,
+                color: Color::WHITE,
+                ..default()
+            },
+        ))
+        .id();
+
+    let button_style = Style {
+        width: Val::Px(250.0),
+        height: Val::Px(65.0),
+        margin: UiRect::all(Val::Px(20.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let button_text_style = TextStyle {
+        font_size: 24.0,
+        ..default()
+    };
+    let button = ButtonBundle {
+        style: button_style.clone(),
+        background_color: NORMAL_BUTTON.into(),
+        ..default()
+    };
+
+    let mut buttons = vec![title];
+    for (label, action) in [
+        ("New Game", StartMenuButtons::NewGame),
+        ("Settings", StartMenuButtons::Settings),
+        ("Quit", StartMenuButtons::Quit),
+    ] {
+        let button = commands
+            .spawn(button.clone())
+            .with_children(|parent| {
+                parent.spawn(TextBundle::from_section(label, button_text_style.clone()));
+            })
+            .insert(action)
+            .id();
+        buttons.push(button);
+    }
+    commands.entity(root).push_children(&buttons);
+}
+
+fn despawn_menu_camera(mut commands: Commands, cameras: Query<Entity, With<MenuCamera>>) {
+    for entity in cameras.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn apply_start_menu_button_system(
+    interaction_query: Query<(&Interaction, &StartMenuButtons), ChangedButton>,
+    mut gamestate: ResMut<NextState<GameState>>,
+    mut event_writer: EventWriter<AppExit>,
+) {
+    for (interaction, selected) in interaction_query.iter() {
+        match (*interaction, selected) {
+            (Interaction::Pressed, StartMenuButtons::NewGame) => gamestate.set(GameState::Playing),
+            (Interaction::Pressed, StartMenuButtons::Settings) => {
+                gamestate.set(GameState::Settings)
+            }
+            (Interaction::Pressed, StartMenuButtons::Quit) => event_writer.send(AppExit),
+            (_, _) => (),
+        };
+    }
+}
+
+fn binding_label(action: PlayerAction, input_map: &InputMap, rebinding: &Rebinding) -> String {
+    if rebinding.action == Some(action) {
+        return format!("{}: press an input (Esc cancels)", action.name());
+    }
+    let bindings: Vec<String> = input_map
+        .bindings(action)
+        .iter()
+        .map(InputBinding::label)
+        .collect();
+    if bindings.is_empty() {
+        format!("{}: unbound", action.name())
+    } else {
+        format!("{}: {}", action.name(), bindings.join(", "))
+    }
+}
+
+fn setup_settings_menu(
+    mut commands: Commands,
+    input_map: Res<InputMap>,
+    rebinding: Res<Rebinding>,
+) {
+    let root = commands
+        .spawn((NodeBundle {
+            background_color: BackgroundColor(Color::MIDNIGHT_BLUE.with_a(0.9)),
+            z_index: ZIndex::Global(i32::MAX),
+            style: Style {
+                position_type: PositionType::Absolute,
+                right: Val::Percent(25.),
+                top: Val::Percent(5.),
+                bottom: Val::Auto,
+                left: Val::Percent(25.),
+                padding: UiRect::all(Val::Px(4.0)),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        },))
+        .insert(MenuRoot)
+        .insert(Name::new("SettingsMenuRoot"))
+        .id();
+
+    let title = commands
+        .spawn(TextBundle::from_section(
+            "Controls",
+            TextStyle {
+                font_size: 36.0,
+                color: Color::WHITE,
+                ..default()
+            },
+        ))
+        .id();
+
+    // Thirteen actions have to fit, so these are a lot slimmer than the menu buttons.
+    let button = ButtonBundle {
+        style: Style {
+            width: Val::Percent(100.0),
+            height: Val::Px(30.0),
+            margin: UiRect::all(Val::Px(3.0)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        background_color: NORMAL_BUTTON.into(),
+        ..default()
+    };
+    let button_text_style = TextStyle {
+        font_size: 18.0,
+        ..default()
+    };
+
+    let mut children = vec![title];
+    for action in PlayerAction::ALL {
+        let label = binding_label(action, &input_map, &rebinding);
+        let row = commands
+            .spawn(button.clone())
+            .with_children(|parent| {
+                parent.spawn(TextBundle::from_section(label, button_text_style.clone()));
+            })
+            .insert(RebindButton(action))
+            .id();
+        children.push(row);
+    }
+    for (label, action) in [
+        ("Reset Defaults", SettingsButtons::ResetDefaults),
+        ("Back", SettingsButtons::Back),
+    ] {
+        let button = commands
+            .spawn(button.clone())
+            .with_children(|parent| {
+                parent.spawn(TextBundle::from_section(label, button_text_style.clone()));
+            })
+            .insert(action)
+            .id();
+        children.push(button);
+    }
+    commands.entity(root).push_children(&children);
+}
+
+fn apply_settings_button_system(
+    interaction_query: Query<
+        (
+            &Interaction,
+            Option<&SettingsButtons>,
+            Option<&RebindButton>,
+        ),
+        ChangedButton,
     >,
+    mut input_map: ResMut<InputMap>,
+    mut rebinding: ResMut<Rebinding>,
     mut gamestate: ResMut<NextState<GameState>>,
-    mut event_writer: EventWriter<BladeEvent>,
 ) {
-    for (entity, interaction, name) in interaction_query.iter_mut() {
-        match (entity, *interaction, name) {
-            (entity, Interaction::Pressed, name) => {
-                event_writer.send(BladeEvent);
-                gamestate.set(GameState::Playing);
-                // commands.entity(entity).despawn_recursive()
-
-                // if name == "menuitem name" do the thing
+    for (interaction, selected, rebind) in interaction_query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match (selected, rebind) {
+            (_, Some(RebindButton(action))) => {
+                rebinding.action = Some(*action);
+                rebinding.armed = false;
+            }
+            (Some(SettingsButtons::ResetDefaults), _) => {
+                *input_map = InputMap::default();
+                input_map.save();
+            }
+            (Some(SettingsButtons::Back), _) => gamestate.set(GameState::StartMenu),
+            (None, None) => (),
+        }
+    }
+}
+
+// Reads raw input on purpose, whatever gets pressed next becomes the binding.
+fn listen_for_rebinding(
+    mut rebinding: ResMut<Rebinding>,
+    mut input_map: ResMut<InputMap>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+) {
+    let Some(action) = rebinding.action else {
+        return;
+    };
+    if !rebinding.armed {
+        // Don't bind the mouse click that opened this up.
+        if mouse_input.get_pressed().next().is_none() {
+            rebinding.armed = true;
+        }
+        return;
+    }
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        rebinding.action = None;
+        return;
+    }
+
+    let stick_pushed = || {
+        gamepads.iter().find_map(|gamepad| {
+            [
+                GamepadAxisType::LeftStickX,
+                GamepadAxisType::LeftStickY,
+                GamepadAxisType::RightStickX,
+                GamepadAxisType::RightStickY,
+                GamepadAxisType::LeftZ,
+                GamepadAxisType::RightZ,
+            ]
+            .into_iter()
+            .find_map(|axis| {
+                let value = gamepad_axes.get(GamepadAxis::new(gamepad, axis))?;
+                (value.abs() > 0.5).then_some(InputBinding::GamepadAxis {
+                    axis,
+                    positive: value > 0.,
+                })
+            })
+        })
+    };
+    let binding = keyboard_input
+        .get_just_pressed()
+        .next()
+        .map(|key| InputBinding::Key(*key))
+        .or_else(|| {
+            mouse_input
+                .get_just_pressed()
+                .next()
+                .map(|button| InputBinding::Mouse(*button))
+        })
+        .or_else(|| {
+            gamepad_buttons
+                .get_just_pressed()
+                .next()
+                .map(|button| InputBinding::GamepadButton(button.button_type))
+        })
+        .or_else(stick_pushed);
+    if let Some(binding) = binding {
+        input_map.rebind(action, binding);
+        input_map.save();
+        rebinding.action = None;
+    }
+}
+
+fn update_binding_labels(
+    input_map: Res<InputMap>,
+    rebinding: Res<Rebinding>,
+    buttons: Query<(&RebindButton, &Children)>,
+    mut text_query: Query<&mut Text>,
+) {
+    if !input_map.is_changed() && !rebinding.is_changed() {
+        return;
+    }
+    for (RebindButton(action), children) in buttons.iter() {
+        for &child in children.iter() {
+            if let Ok(mut text) = text_query.get_mut(child) {
+                text.sections[0].value = binding_label(*action, &input_map, &rebinding);
             }
-            (_, _, _) => (),
         }
     }
 }
+
+fn cancel_rebinding(mut rebinding: ResMut<Rebinding>) {
+    *rebinding = Rebinding::default();
+}
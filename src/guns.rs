@@ -1,7 +1,13 @@
+use crate::controls::{ActionState, PlayerAction};
 use crate::game_ui::{GameInterfacePlugin, GameRuntime, GameState};
-use crate::player::Player;
+use crate::mobs::{Enemy, Shield};
+use crate::player::{Player, WindowSize};
+use crate::pool::Pool;
+use crate::status::Embedded;
 
 use bevy::ecs::schedule::MultiThreadedExecutor;
+use bevy::ecs::system::EntityCommands;
+use bevy::input::mouse::MouseWheel;
 use bevy::render::render_resource::AsBindGroupShaderType;
 use bevy::sprite::MaterialMesh2dBundle;
 use bevy::{prelude::*, sprite};
@@ -10,36 +16,219 @@ use bevy_rapier2d::prelude::*;
 use rand::{thread_rng, Rng};
 use std::iter::Empty;
 
-const COOLDOWN_DURATION_MS: u64 = 100;
+pub const MAX_WEAPON_SLOTS: usize = 4;
+const BEAM_DPS: f32 = 60.;
+const BEAM_RANGE: f32 = 900.;
+const BEAM_WIDTH: f32 = 12.;
+// Boids tuning, distances in pixels.
+const BOID_NEIGHBOR_RADIUS: f32 = 120.;
+const BOID_SEPARATION_RADIUS: f32 = 40.;
+// Drones further than this from the ship get pulled back in.
+const BOID_LEASH_RADIUS: f32 = 150.;
+const BOID_COHESION: f32 = 1.0;
+const BOID_ALIGNMENT: f32 = 1.0;
+const BOID_SEPARATION: f32 = 1.5;
+const BOID_LEASH: f32 = 2.0;
+const DRONE_RANGE: f32 = 500.;
+const WEAPON_SLOT_ACTIONS: [PlayerAction; MAX_WEAPON_SLOTS] = [
+    PlayerAction::Weapon1,
+    PlayerAction::Weapon2,
+    PlayerAction::Weapon3,
+    PlayerAction::Weapon4,
+];
+const BASE_PROJECTILE_DAMAGE: f32 = 10.;
 pub const PROJECTILE_LIMIT: i32 = 40;
+// Extra projectiles spawned whenever the pool runs low, 0 caps it at PROJECTILE_LIMIT.
+const PROJECTILE_POOL_GROWTH: usize = 10;
 pub const BALL_SIZE: Vec3 = Vec3::splat(30.);
+// Sprite size and collider radius of a projectile before any Blade event stretches it.
+const PROJECTILE_SPRITE_SIZE: Vec2 = Vec2::splat(1.5);
+const PROJECTILE_RADIUS: f32 = 0.6;
+const TRACKING_RANGE: f32 = 600.;
+// Half angle of the cone in front of a bullet that it will home in on.
+const TRACKING_CONE: f32 = std::f32::consts::FRAC_PI_4;
+// How long a piercing bullet stays a sensor to get clear of the enemy it went through.
+const PIERCE_GRACE_SECS: f32 = 0.2;
+const PROJECTILE_RANGE: f32 = 1200.;
+const PROJECTILE_LIFETIME: f32 = 1.5;
+const RICOCHET_RESTITUTION: f32 = 0.8;
+// Bullets go back to the pool on the bounce after this many.
+const MAX_BOUNCES: u32 = 3;
 
 #[derive(Event)]
 pub struct BladeEvent;
-#[derive(Resource)]
-pub struct ProjectilePool(Vec<Entity>);
-#[derive(Component)]
+pub type ProjectilePool = Pool<Projectile>;
+#[derive(Component, Clone)]
 pub struct Projectile {
     pub damage: f32,
     pub damage_modifier: f32,
-    pub cooldown: Timer,
+    // Distance from `origin` before the bullet goes back to the pool.
     pub range: f32,
+    // Seconds of game time before the bullet goes back to the pool.
+    pub lifetime: f32,
+    pub origin: Vec2,
+    pub fired_at: f32,
     pub size: Vec3,
+    // Fraction of the speed into a surface kept when ricocheting off it.
+    pub restitution: f32,
+    pub max_bounces: u32,
+    pub bounces: u32,
+    // Velocity before this frame's physics step, the solver has already bounced it by the time
+    // collisions are handled.
+    pub incoming: Vec2,
+    // Set from the weapon that fired it.
+    pub weapon_damage: f32,
+    pub weapon_pierce: f32,
+}
+
+impl Default for Projectile {
+    fn default() -> Self {
+        Projectile {
+            damage: BASE_PROJECTILE_DAMAGE,
+            damage_modifier: 1.2,
+            range: PROJECTILE_RANGE,
+            lifetime: PROJECTILE_LIFETIME,
+            origin: Vec2::ZERO,
+            fired_at: 0.,
+            size: BALL_SIZE,
+            restitution: RICOCHET_RESTITUTION,
+            max_bounces: MAX_BOUNCES,
+            bounces: 0,
+            incoming: Vec2::ZERO,
+            weapon_damage: 1.,
+            weapon_pierce: 0.,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WeaponKind {
+    Blaster,
+    SpreadGun,
+    Blade,
+    Beam,
+}
+
+impl WeaponKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WeaponKind::Blaster => "Blaster",
+            WeaponKind::SpreadGun => "Spread",
+            WeaponKind::Blade => "Blade",
+            WeaponKind::Beam => "Beam",
+        }
+    }
+}
+
+/// Something the player shoots with, keeping its own fire rate and projectile look.
+#[derive(Component)]
+pub struct Weapon {
+    pub kind: WeaponKind,
+    pub cooldown: Timer,
+    pub texture: Handle<Image>,
+    // Multiplies Projectile::damage for shots from this weapon, or BEAM_DPS for a beam.
+    pub damage: f32,
+    // Projectiles per shot before Multishot, fanned out `spread` radians apart.
+    pub pellets: i32,
+    pub spread: f32,
+    pub impulse: f32,
+    // Added to Blade::pierce for shots from this weapon.
+    pub pierce: f32,
+    // Share of the momentum given to the projectiles that kicks the ship back, 0 for none.
+    pub recoil: f32,
+}
+
+impl Weapon {
+    pub fn new(kind: WeaponKind, asset_server: &AssetServer) -> Self {
+        let (cooldown_secs, texture, damage, pellets, spread, impulse, pierce, recoil) = match kind
+        {
+            // Enough pierce to bite into every armored enemy, but not to go through.
+            WeaponKind::Blaster => (0.1, "Lasers/08.png", 1., 1, 0., 10000., 1., 0.),
+            // Heavy enough to double as a dash away from the cursor.
+            // Pellets glance off anything armored until the Blade upgrade adds pierce.
+            WeaponKind::SpreadGun => (0.45, "Lasers/11.png", 0.6, 5, 0.12, 9000., 0.5, 0.02),
+            WeaponKind::Blade => (0.35, "Lasers/15.png", 2.5, 1, 0., 7000., 2., 0.03),
+            // Hitscan, fires every frame the trigger is held.
+            WeaponKind::Beam => (0., "Lasers/20.png", 1., 0, 0., 0., 1., 0.),
+        };
+        let mut cooldown = Timer::from_seconds(cooldown_secs, TimerMode::Once);
+        // Ready to fire straight away.
+        cooldown.tick(cooldown.duration());
+        Weapon {
+            kind,
+            cooldown,
+            texture: asset_server.load(texture),
+            damage,
+            pellets,
+            spread,
+            impulse,
+            pierce,
+            recoil,
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.cooldown.finished()
+    }
+
+    pub fn make_ready(&mut self) {
+        self.cooldown.reset();
+        let duration = self.cooldown.duration();
+        self.cooldown.tick(duration);
+    }
+}
+
+#[derive(Component)]
+struct BeamSprite;
+
+/// The weapons the player carries, in slot order, and which one is in hand.
+#[derive(Component)]
+pub struct WeaponSlots {
+    pub weapons: Vec<Entity>,
+    pub active: usize,
+}
+
+impl WeaponSlots {
+    pub fn active_weapon(&self) -> Option<Entity> {
+        self.weapons.get(self.active).copied()
+    }
+
+    /// Adds a weapon to the next free slot, false once every slot is taken.
+    pub fn equip(&mut self, weapon: Entity) -> bool {
+        if self.weapons.len() >= MAX_WEAPON_SLOTS {
+            return false;
+        }
+        self.weapons.push(weapon);
+        true
+    }
+
+    pub fn select(&mut self, slot: usize) {
+        if slot < self.weapons.len() {
+            self.active = slot;
+        }
+    }
+
+    // Steps through the slots, wrapping around at either end.
+    pub fn cycle(&mut self, step: i32) {
+        let len = self.weapons.len() as i32;
+        if len > 0 {
+            self.active = (self.active as i32 + step).rem_euclid(len) as usize;
+        }
+    }
 }
 #[derive(Component)]
 pub struct Multishot {
-    count: i32,
-    spread: f32,
-    processed: bool,
+    pub count: i32,
+    pub spread: f32,
+    pub processed: bool,
 }
 pub struct Burst {
     rounds: i32,
     damage_modifier: f32,
 }
-#[derive(Component)]
+#[derive(Component, Clone)]
 pub struct Burn {
-    dot: f32,
-    trail_length: f32,
+    pub dot: f32,
+    pub trail_length: f32,
 }
 #[derive(Component, Debug, Clone)]
 pub struct Blade {
@@ -49,58 +238,144 @@ pub struct Blade {
     pub swing_speed: f32,
     pub pierce: f32,
 }
+
+impl Default for Blade {
+    fn default() -> Self {
+        Blade {
+            slash_dmg: 1.2,
+            bleed: 1.2,
+            length: 2.,
+            swing_speed: 1.2,
+            // Only the Blade upgrade adds pierce, shots otherwise get their weapon's.
+            pierce: 0.,
+        }
+    }
+}
+
+/// How a hit resolves against an enemy's armor.
+pub enum Penetration {
+    // Bounces off without doing any damage.
+    Glance,
+    // Sticks in the enemy and bleeds it.
+    Embed,
+    // Carries on through with damage reduced by the armor.
+    PierceThrough,
+}
+
+impl Penetration {
+    pub fn resolve(pierce: f32, armor: f32) -> Self {
+        if pierce <= armor {
+            Penetration::Glance
+        } else if pierce < armor * 2. {
+            Penetration::Embed
+        } else {
+            Penetration::PierceThrough
+        }
+    }
+}
+
+/// A bullet passing through `through`, it ignores that enemy until the grace period ends.
 #[derive(Component)]
+pub struct Piercing {
+    pub through: Entity,
+    grace: Timer,
+}
+
+impl Piercing {
+    pub fn new(through: Entity) -> Self {
+        Piercing {
+            through,
+            grace: Timer::from_seconds(PIERCE_GRACE_SECS, TimerMode::Once),
+        }
+    }
+}
+#[derive(Component, Clone)]
 pub struct Tracking {
-    pull_strength: f32,
-    damage_modifier: f32, // reduce damage by %
+    pub pull_strength: f32,
+    pub damage_modifier: f32, // reduce damage by %
 }
-#[derive(Component)]
+#[derive(Component, Clone)]
 pub struct Emp {
-    stun_length: f32,
-    shield_damage: f32,
+    pub stun_length: f32,
+    pub shield_damage: f32,
 }
-// TODO: minion flocking.
+/// A drone flocking around the player, it fires at enemies with its own Weapon.
 #[derive(Component)]
-pub struct PlayerBoyd {}
+pub struct PlayerBoyd {
+    pub velocity: Vec2,
+    pub max_speed: f32,
+    pub max_force: f32,
+}
+
+impl Default for PlayerBoyd {
+    fn default() -> Self {
+        PlayerBoyd {
+            velocity: Vec2::ZERO,
+            max_speed: 350.,
+            max_force: 900.,
+        }
+    }
+}
+
+/// Adds this many drones to the player's flock.
+#[derive(Event)]
+pub struct MinionEvent(pub u32);
 
 pub struct WeaponPlugin;
 impl Plugin for WeaponPlugin {
     fn build(&self, app: &mut App) {
-        app.add_event::<BladeEvent>()
-            .insert_resource(ProjectilePool(Vec::new()))
-            .insert_resource(ShootingCooldown {
-                last_shot_time: Instant::now(),
-                cooldown_duration: Duration::from_millis(COOLDOWN_DURATION_MS),
-            })
+        app.add_plugins(MinionPlugin)
+            .add_event::<BladeEvent>()
+            .insert_resource(ProjectilePool::new(PROJECTILE_POOL_GROWTH))
             .add_systems(
-                Startup,
-                (setup_projectiles).run_if(in_state(GameState::Playing)),
+                OnEnter(GameState::Playing),
+                setup_projectiles.run_if(not(any_with_component::<Projectile>())),
             )
             .add_systems(
                 Update,
-                (upgrade_weapon, shoot_projectile).run_if(in_state(GameState::Playing)),
+                (
+                    upgrade_weapon,
+                    // The player is only around once OnEnter(Playing) has been applied.
+                    setup_weapons.run_if(not(any_with_component::<WeaponSlots>())),
+                    switch_weapon.before(shoot_projectile),
+                    tick_weapons.before(shoot_projectile),
+                    fire_beam.after(switch_weapon),
+                    shoot_projectile,
+                    grow_projectile_pool.after(shoot_projectile),
+                    end_piercing,
+                    record_incoming_velocity,
+                )
+                    .run_if(in_state(GameState::Playing)),
             )
             .add_systems(
                 FixedUpdate,
-                // TODO: sometimes this runs after the gamestate is updated, removing paused projectiles.
+                // Only game time and distance are checked, so bullets frozen by a pause survive it.
                 despawn_projectile.run_if(in_state(GameState::Playing)),
             )
             .add_systems(
                 FixedUpdate,
                 apply_blade_event.run_if(on_event::<BladeEvent>()),
+            )
+            .add_systems(
+                FixedUpdate,
+                track_enemies.run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                OnExit(GameState::GameOver),
+                (reset_projectiles, reset_weapons),
             );
     }
 }
 
 fn setup_projectiles(
     mut commands: Commands,
-    mut spawnpool: ResMut<ProjectilePool>,
+    mut pool: ResMut<ProjectilePool>,
     asset_server: Res<AssetServer>,
 ) {
     let sprite = SpriteBundle {
         texture: asset_server.load("Lasers/08.png"),
         sprite: Sprite {
-            custom_size: Some(Vec2::splat(1.5)),
+            custom_size: Some(PROJECTILE_SPRITE_SIZE),
             ..default()
         },
         transform: Transform::from_translation(Vec3::new(10000., 10000., 2.)),
@@ -113,151 +388,564 @@ fn setup_projectiles(
     };
     // Spawn a bunch of projectiles.
     for _ in 1..=PROJECTILE_LIMIT {
-        let entity = commands
-            .spawn(Collider::ball(0.6))
-            .insert(sprite.clone())
-            .insert(Sleeping {
-                sleeping: true,
-                ..default()
-            })
-            .insert(Blade {
-                slash_dmg: 1.2,
-                bleed: 1.2,
-                length: 2.,
-                swing_speed: 1.2,
-                pierce: 1.0,
-            })
-            .insert(Projectile {
-                damage: 10.,
-                damage_modifier: 1.2,
-                cooldown: Timer::from_seconds(0.5, TimerMode::Repeating),
-                range: 20.,
-                size: BALL_SIZE.clone(),
-            })
-            .insert(Visibility::Hidden)
-            .insert(ExternalImpulse {
-                impulse: Vec2::new(0., 0.),
-                torque_impulse: 0.0,
-            })
-            .insert(Damping {
-                linear_damping: 3.5,
-                angular_damping: 5.0,
-            })
-            .insert(RigidBody::Dynamic)
-            .insert(AdditionalMassProperties::Mass(2.0))
-            .insert(GravityScale(0.))
-            .insert(Velocity::zero())
-            .insert(CollisionGroups::new(Group::GROUP_2, Group::GROUP_3))
-            .insert(SolverGroups::new(Group::GROUP_2, Group::GROUP_3))
-            .insert(ActiveEvents::COLLISION_EVENTS)
-            .id();
+        let entity = spawn_projectile(
+            &mut commands,
+            sprite.clone(),
+            Collider::ball(PROJECTILE_RADIUS),
+            Projectile::default(),
+            Blade::default(),
+        )
+        .id();
 
-        spawnpool.0.push(entity);
+        pool.add(entity);
     }
 }
 
-fn upgrade_weapon(keyboard_input: Res<Input<KeyCode>>, mut event_writer: EventWriter<BladeEvent>) {
-    if keyboard_input.just_pressed(KeyCode::B) {
+// A hidden projectile ready for the pool.
+fn spawn_projectile<'w, 's, 'a>(
+    commands: &'a mut Commands<'w, 's>,
+    sprite: SpriteBundle,
+    collider: Collider,
+    projectile: Projectile,
+    blade: Blade,
+) -> EntityCommands<'w, 's, 'a> {
+    let mut entity = commands.spawn(collider);
+    entity
+        .insert(sprite)
+        .insert(Sleeping {
+            sleeping: true,
+            ..default()
+        })
+        .insert(blade)
+        .insert(projectile)
+        .insert(Visibility::Hidden)
+        .insert(ExternalImpulse {
+            impulse: Vec2::new(0., 0.),
+            torque_impulse: 0.0,
+        })
+        .insert(Damping {
+            linear_damping: 3.5,
+            angular_damping: 5.0,
+        })
+        .insert(RigidBody::Dynamic)
+        .insert(AdditionalMassProperties::Mass(2.0))
+        .insert(GravityScale(0.))
+        .insert(Velocity::zero())
+        .insert(CollisionGroups::new(Group::GROUP_2, Group::GROUP_3))
+        .insert(SolverGroups::new(Group::GROUP_2, Group::GROUP_3))
+        .insert(ActiveEvents::COLLISION_EVENTS);
+    entity
+}
+
+// The parts of a pooled projectile that new pool entries copy.
+type ProjectileTemplate = (
+    &'static Projectile,
+    &'static Blade,
+    &'static Sprite,
+    &'static Handle<Image>,
+    &'static Collider,
+    Option<&'static Burn>,
+    Option<&'static Tracking>,
+    Option<&'static Emp>,
+);
+
+// Tops the pool up with copies of a pooled projectile, so new ones keep every upgrade so far.
+fn grow_projectile_pool(
+    mut commands: Commands,
+    mut pool: ResMut<ProjectilePool>,
+    template_query: Query<ProjectileTemplate>,
+) {
+    if !pool.needs_growth() {
+        return;
+    }
+    let Some((projectile, blade, sprite, texture, collider, burn, tracking, emp)) =
+        template_query.iter().next()
+    else {
+        return;
+    };
+    for _ in 0..pool.grow_by {
+        let mut entity = spawn_projectile(
+            &mut commands,
+            SpriteBundle {
+                texture: texture.clone(),
+                sprite: sprite.clone(),
+                transform: Transform::from_translation(Vec3::new(10000., 10000., 2.)),
+                ..default()
+            },
+            collider.clone(),
+            projectile.clone(),
+            blade.clone(),
+        );
+        if let Some(burn) = burn {
+            entity.insert(burn.clone());
+        }
+        if let Some(tracking) = tracking {
+            entity.insert(tracking.clone());
+        }
+        if let Some(emp) = emp {
+            entity.insert(emp.clone());
+        }
+        let entity = entity.id();
+        pool.add(entity);
+    }
+    debug!(
+        "Grew projectile pool to {} ({} in use)",
+        pool.len(),
+        pool.in_use()
+    );
+}
+
+fn upgrade_weapon(actions: Res<ActionState>, mut event_writer: EventWriter<BladeEvent>) {
+    if actions.just_pressed(PlayerAction::Blade) {
         event_writer.send(BladeEvent)
     }
 }
 
-use std::time::{Duration, Instant};
-#[derive(Resource, Debug)]
-struct ShootingCooldown {
-    last_shot_time: Instant,
-    cooldown_duration: Duration,
+fn setup_weapons(
+    mut commands: Commands,
+    player_query: Query<Entity, With<Player>>,
+    asset_server: Res<AssetServer>,
+) {
+    let Ok(player) = player_query.get_single() else {
+        return;
+    };
+    let mut slots = WeaponSlots {
+        weapons: Vec::new(),
+        active: 0,
+    };
+    let mut beam_texture = Handle::default();
+    for kind in [
+        WeaponKind::Blaster,
+        WeaponKind::SpreadGun,
+        WeaponKind::Blade,
+        WeaponKind::Beam,
+    ] {
+        let weapon = Weapon::new(kind, &asset_server);
+        if kind == WeaponKind::Beam {
+            beam_texture = weapon.texture.clone();
+        }
+        let weapon = commands.spawn(weapon).insert(Name::new(kind.name())).id();
+        commands.entity(player).add_child(weapon);
+        slots.equip(weapon);
+    }
+    commands.entity(player).insert(slots);
+
+    // Parented to the ship so it always points the way it's facing.
+    let beam = commands
+        .spawn(SpriteBundle {
+            texture: beam_texture,
+            sprite: Sprite {
+                custom_size: Some(Vec2::new(BEAM_WIDTH, 0.)),
+                ..default()
+            },
+            visibility: Visibility::Hidden,
+            ..default()
+        })
+        .insert(BeamSprite)
+        .id();
+    commands.entity(player).add_child(beam);
 }
 
+type BeamParts = (
+    &'static mut Transform,
+    &'static mut Sprite,
+    &'static mut Visibility,
+);
+
+// Casts the beam from the ship along its facing, burning the first enemy in the way.
+#[allow(clippy::too_many_arguments)]
+fn fire_beam(
+    player_query: Query<(&Transform, &WeaponSlots), With<Player>>,
+    weapon_query: Query<&Weapon>,
+    mut beam_query: Query<BeamParts, (With<BeamSprite>, Without<Player>)>,
+    mut enemy_query: Query<(&mut Enemy, Option<&Shield>)>,
+    actions: Res<ActionState>,
+    rapier_context: Res<RapierContext>,
+    time: Res<Time>,
+) {
+    let Ok((mut beam_transform, mut beam_sprite, mut beam_visibility)) =
+        beam_query.get_single_mut()
+    else {
+        return;
+    };
+    let Ok((player_transform, slots)) = player_query.get_single() else {
+        return;
+    };
+    let beam = slots
+        .active_weapon()
+        .and_then(|weapon| weapon_query.get(weapon).ok())
+        .filter(|weapon| weapon.kind == WeaponKind::Beam);
+    let firing = actions.pressed(PlayerAction::Fire);
+    let Some(beam) = beam.filter(|_| firing) else {
+        *beam_visibility = Visibility::Hidden;
+        return;
+    };
+
+    let origin = player_transform.translation.xy();
+    let facing = player_transform.rotation.mul_vec3(Vec3::Y).xy();
+    let filter = QueryFilter::new()
+        .exclude_sensors()
+        .groups(CollisionGroups::new(Group::GROUP_2, Group::GROUP_3));
+    let length = match rapier_context.cast_ray(origin, facing, BEAM_RANGE, true, filter) {
+        Some((hit, distance)) => {
+            if let Ok((mut enemy, shield)) = enemy_query.get_mut(hit) {
+                // Shields and armor stop the beam the same as bullets, it just can't stick
+                // or carry on through.
+                let armor_modifier = if shield.is_some_and(Shield::is_up) {
+                    0.
+                } else {
+                    match Penetration::resolve(beam.pierce, enemy.armor) {
+                        Penetration::Glance => 0.,
+                        Penetration::Embed => 1.,
+                        Penetration::PierceThrough => 1. - enemy.armor / beam.pierce,
+                    }
+                };
+                enemy.health -= BEAM_DPS * beam.damage * armor_modifier * time.delta_seconds();
+            }
+            distance
+        }
+        None => BEAM_RANGE,
+    };
+
+    // The sprite is a child of the ship, so it only needs stretching along local Y.
+    beam_sprite.custom_size = Some(Vec2::new(BEAM_WIDTH, length));
+    beam_transform.translation = Vec3::new(0., length / 2., -0.1);
+    *beam_visibility = Visibility::Visible;
+}
+
+// Slot actions pick a slot, the mouse wheel and next/previous actions cycle through them.
+fn switch_weapon(
+    actions: Res<ActionState>,
+    mut scroll_events: EventReader<MouseWheel>,
+    mut slots_query: Query<&mut WeaponSlots>,
+) {
+    let Ok(mut slots) = slots_query.get_single_mut() else {
+        return;
+    };
+    for (slot, action) in WEAPON_SLOT_ACTIONS.iter().enumerate() {
+        if actions.just_pressed(*action) {
+            slots.select(slot);
+        }
+    }
+    let scrolled: f32 = scroll_events.read().map(|event| event.y).sum();
+    if scrolled > 0. || actions.just_pressed(PlayerAction::PreviousWeapon) {
+        slots.cycle(-1);
+    } else if scrolled < 0. || actions.just_pressed(PlayerAction::NextWeapon) {
+        slots.cycle(1);
+    }
+}
+
+// Cooldowns run on virtual time, so they stop with the game and follow any time scaling.
+fn tick_weapons(mut weapon_query: Query<&mut Weapon>, time: Res<Time>) {
+    for mut weapon in weapon_query.iter_mut() {
+        weapon.cooldown.tick(time.delta());
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn shoot_projectile(
-    mut projectile_query: Query<
+    mut projectile_query: Query<LaunchableProjectile, (With<Projectile>, Without<Player>)>,
+    mut player_query: Query<
         (
+            &Transform,
+            &WeaponSlots,
+            Option<&Multishot>,
             &mut ExternalImpulse,
-            &mut Velocity,
-            &mut Transform,
-            &mut Visibility,
         ),
-        (With<Projectile>, Without<Player>),
+        With<Player>,
     >,
-    player_query: Query<&Transform, With<Player>>,
-    keyboard_input: Res<Input<KeyCode>>,
-    mouse_input: Res<Input<MouseButton>>,
+    actions: Res<ActionState>,
     cursor: Res<CursorInfo>,
-    mut cooldown: ResMut<ShootingCooldown>,
+    mut weapon_query: Query<&mut Weapon>,
+    mut pool: ResMut<ProjectilePool>,
+    runtime: Res<GameRuntime>,
 ) {
-    let mut spawn_limit = PROJECTILE_LIMIT as usize;
-    if keyboard_input.pressed(KeyCode::S) || mouse_input.pressed(MouseButton::Left) {
-        let current_time = Instant::now();
-        let time_since_last_shot = current_time - cooldown.last_shot_time;
-
-        if time_since_last_shot >= cooldown.cooldown_duration {
-            for (i, (mut ext_impulse, mut velocity, mut transform, mut visibility)) in
-                projectile_query.iter_mut().enumerate()
-            {
-                if *visibility == Visibility::Hidden {
-                    match cursor.position() {
-                        Some(cursor_direction) => {
-                            if i < spawn_limit {
-                                *visibility = Visibility::Visible;
-                                *velocity = Velocity::zero();
-                                // Retrieve player position
-                                let player_transform = player_query.single();
-
-                                // Set projectile transform to player position
-                                transform.translation = player_transform.translation;
-                                transform.scale = BALL_SIZE;
-
-                                // Calculate direction vector from projectile position to cursor position
-                                let direction = cursor_direction - transform.translation.truncate();
-
-                                // Normalize direction vector
-                                let normalized_direction = direction.normalize();
-
-                                // Apply force in the direction of the normalized direction
-                                ext_impulse.impulse = normalized_direction * 10000.0;
-
-                                // Update projectile transform to face the cursor direction
-                                // info!("BEFORE {:?}", transform.rotation);
-                                let d = (player_transform.translation.xy() - cursor_direction)
-                                    .normalize();
-                                let proj_rotate = Quat::from_rotation_arc(-Vec3::Z, d.extend(0.));
-                                transform.rotation = player_transform.rotation;
-                                // transform.rotate_local_z(-80.);
-                                // transform.rotate_z(-80.);
-                                transform.rotate_z(1.5);
-                                // transform.rotation = proj_rotate;
-
-                                // info!("AFTER {:?}", transform.rotation);
-
-                                cooldown.last_shot_time = current_time;
-                            }
-                            spawn_limit = i;
-                        }
-                        _ => (),
-                    }
-                }
+    if actions.pressed(PlayerAction::Fire) {
+        let Ok((player_transform, slots, multishot, mut player_impulse)) =
+            player_query.get_single_mut()
+        else {
+            return;
+        };
+        let Some(Ok(mut weapon)) = slots
+            .active_weapon()
+            .map(|weapon| weapon_query.get_mut(weapon))
+        else {
+            return;
+        };
+
+        // Beams are handled by fire_beam.
+        if weapon.kind == WeaponKind::Beam {
+            return;
+        }
+
+        if weapon.is_ready() {
+            // Multishot adds its extra projectiles on top of the weapon's own.
+            let (count, spread) = match multishot {
+                Some(multishot) => (
+                    weapon.pellets + multishot.count.max(1) - 1,
+                    weapon.spread.max(multishot.spread),
+                ),
+                None => (weapon.pellets, weapon.spread),
+            };
+
+            // Aim at the cursor or along the right stick, straight ahead if there's neither.
+            let aim = actions
+                .aim_direction(player_transform.translation.truncate(), cursor.position())
+                .unwrap_or_else(|| player_transform.rotation.mul_vec3(Vec3::Y).xy());
+
+            let mut fired = 0;
+            let mut momentum = Vec2::ZERO;
+            while fired < count {
+                let Some(entity) = pool.acquire() else {
+                    break;
+                };
+                let Ok(launch) = projectile_query.get_mut(entity) else {
+                    // Hand it straight back rather than leaking it, and stop before acquiring it again.
+                    pool.release(entity);
+                    break;
+                };
+                // Fan the volley out evenly on both sides of the aim.
+                let offset = (fired as f32 - (count - 1) as f32 / 2.) * spread;
+                let direction = Vec2::from_angle(offset).rotate(aim);
+
+                launch_projectile(
+                    launch,
+                    &weapon,
+                    player_transform.translation,
+                    direction,
+                    runtime.0.elapsed_secs(),
+                );
+                momentum += direction * weapon.impulse;
+
+                fired += 1;
+            }
+            if fired > 0 {
+                weapon.cooldown.reset();
+                // Whatever the volley carries off (projectile mass times speed) pushes the ship back.
+                player_impulse.impulse -= momentum * weapon.recoil;
             }
         }
     }
 }
 
+// Query data whose items are a ProjectileLaunch.
+type LaunchableProjectile = (
+    &'static mut ExternalImpulse,
+    &'static mut Velocity,
+    &'static mut Transform,
+    &'static mut Visibility,
+    &'static mut Projectile,
+    &'static mut Handle<Image>,
+);
+
+type ProjectileLaunch<'a> = (
+    Mut<'a, ExternalImpulse>,
+    Mut<'a, Velocity>,
+    Mut<'a, Transform>,
+    Mut<'a, Visibility>,
+    Mut<'a, Projectile>,
+    Mut<'a, Handle<Image>>,
+);
+
+// Puts a pooled projectile in flight from `origin` along `direction`, using `weapon`'s stats.
+fn launch_projectile(
+    (mut ext_impulse, mut velocity, mut transform, mut visibility, mut projectile, mut texture): ProjectileLaunch,
+    weapon: &Weapon,
+    origin: Vec3,
+    direction: Vec2,
+    fired_at: f32,
+) {
+    *visibility = Visibility::Visible;
+    *velocity = Velocity::zero();
+    projectile.bounces = 0;
+    projectile.incoming = Vec2::ZERO;
+    projectile.origin = origin.xy();
+    projectile.fired_at = fired_at;
+    projectile.weapon_damage = weapon.damage;
+    projectile.weapon_pierce = weapon.pierce;
+    *texture = weapon.texture.clone();
+
+    transform.translation = origin;
+    transform.scale = BALL_SIZE;
+    transform.rotation = Quat::from_rotation_z(direction.y.atan2(direction.x));
+
+    ext_impulse.impulse = direction * weapon.impulse;
+}
+
+// Returns bullets to the pool once they've gone too far, lived too long or left the screen.
 fn despawn_projectile(
     mut projectile_query: Query<
-        (&mut Velocity, &mut Visibility, &mut Transform),
-        (With<Projectile>, Without<Player>),
+        (
+            Entity,
+            &Projectile,
+            &mut Velocity,
+            &mut Visibility,
+            &mut Transform,
+        ),
+        (Without<Player>, Without<Embedded>, Without<Camera>),
     >,
+    camera_query: Query<&Transform, With<Camera>>,
+    mut pool: ResMut<ProjectilePool>,
+    win_size: Res<WindowSize>,
+    runtime: Res<GameRuntime>,
 ) {
-    for (mut bullet_velocity, mut bullet_visibility, mut transform) in projectile_query.iter_mut() {
-        if *bullet_visibility == Visibility::Visible {
-            let linvel = bullet_velocity.linvel;
-
-            if linvel.x.abs() < 25.0 && linvel.y.abs() < 25.0 {
-                debug!("Hiding expired projectile");
-                *bullet_visibility = Visibility::Hidden;
-                *bullet_velocity = Velocity::zero();
-                transform.translation = Vec3::new(10000., 100000., -1.);
-            }
+    let Ok(camera_transform) = camera_query.get_single() else {
+        return;
+    };
+    let camera = camera_transform.translation.xy();
+    let play_area = Rect::new(
+        camera.x + win_size.left_wall,
+        camera.y + win_size.bottom_wall,
+        camera.x + win_size.right_wall,
+        camera.y + win_size.top_wall,
+    );
+    let now = runtime.0.elapsed_secs();
+
+    for (entity, projectile, mut bullet_velocity, mut bullet_visibility, mut transform) in
+        projectile_query.iter_mut()
+    {
+        if *bullet_visibility != Visibility::Visible {
+            continue;
         }
+        let position = transform.translation.xy();
+        if position.distance(projectile.origin) > projectile.range
+            || now - projectile.fired_at > projectile.lifetime
+            || !play_area.contains(position)
+        {
+            debug!("Hiding expired projectile");
+            recycle_projectile(
+                &mut pool,
+                entity,
+                &mut bullet_velocity,
+                &mut bullet_visibility,
+                &mut transform,
+            );
+        }
+    }
+}
+
+fn record_incoming_velocity(mut projectile_query: Query<(&mut Projectile, &Velocity)>) {
+    for (mut projectile, velocity) in projectile_query.iter_mut() {
+        projectile.incoming = velocity.linvel;
+    }
+}
+
+/// Hides a projectile out of the way and frees it up to be fired again.
+pub fn recycle_projectile(
+    pool: &mut ProjectilePool,
+    entity: Entity,
+    velocity: &mut Velocity,
+    visibility: &mut Visibility,
+    transform: &mut Transform,
+) {
+    *visibility = Visibility::Hidden;
+    *velocity = Velocity::zero();
+    transform.translation = Vec3::new(10000., 100000., -1.);
+    pool.release(entity);
+}
+
+// Projectiles still in flight, as opposed to enemies or ones stuck in an enemy.
+type FlyingProjectile = (With<Projectile>, Without<Enemy>, Without<Embedded>);
+
+// Bend Tracking projectiles towards the closest enemy ahead of them.
+fn track_enemies(
+    mut projectile_query: Query<
+        (&mut Transform, &mut Velocity, &Visibility, &Tracking),
+        FlyingProjectile,
+    >,
+    enemy_query: Query<&Transform, With<Enemy>>,
+    time: Res<Time>,
+) {
+    for (mut transform, mut velocity, visibility, tracking) in projectile_query.iter_mut() {
+        if *visibility == Visibility::Hidden {
+            continue;
+        }
+        let heading = velocity.linvel.normalize_or_zero();
+        if heading == Vec2::ZERO {
+            continue;
+        }
+        let position = transform.translation.xy();
+        let target = enemy_query
+            .iter()
+            .map(|enemy_transform| enemy_transform.translation.xy() - position)
+            .filter(|offset| {
+                offset.length() < TRACKING_RANGE
+                    && heading.angle_between(*offset).abs() < TRACKING_CONE
+            })
+            .min_by(|a, b| a.length_squared().total_cmp(&b.length_squared()));
+
+        if let Some(offset) = target {
+            let desired = offset.normalize() * velocity.linvel.length();
+            let pull = (tracking.pull_strength * time.delta_seconds()).min(1.);
+            velocity.linvel = velocity.linvel.lerp(desired, pull);
+            let angle = velocity.linvel.y.atan2(velocity.linvel.x);
+            transform.rotation = Quat::from_rotation_z(angle);
+        }
+    }
+}
+
+fn end_piercing(
+    mut commands: Commands,
+    mut projectile_query: Query<(Entity, &mut Piercing)>,
+    time: Res<Time>,
+) {
+    for (entity, mut piercing) in projectile_query.iter_mut() {
+        if piercing.grace.tick(time.delta()).finished() {
+            commands.entity(entity).remove::<(Piercing, Sensor)>();
+        }
+    }
+}
+
+type ProjectileReset = (
+    Entity,
+    &'static mut Projectile,
+    &'static mut Velocity,
+    &'static mut Visibility,
+    &'static mut Transform,
+    &'static mut Sprite,
+    &'static mut Collider,
+);
+
+// Returns every projectile to the pool and drops level up bonuses.
+fn reset_projectiles(
+    mut commands: Commands,
+    mut projectile_query: Query<ProjectileReset, Without<Player>>,
+    mut pool: ResMut<ProjectilePool>,
+) {
+    for (
+        entity,
+        mut projectile,
+        mut velocity,
+        mut visibility,
+        mut transform,
+        mut sprite,
+        mut collider,
+    ) in projectile_query.iter_mut()
+    {
+        commands
+            .entity(entity)
+            .remove::<(Burn, Tracking, Emp, Piercing, Sensor)>()
+            .insert(Blade::default());
+        projectile.damage = BASE_PROJECTILE_DAMAGE;
+        // Undo what apply_blade_event stretched, grow_projectile_pool copies these.
+        sprite.custom_size = Some(PROJECTILE_SPRITE_SIZE);
+        *collider = Collider::ball(PROJECTILE_RADIUS);
+        recycle_projectile(
+            &mut pool,
+            entity,
+            &mut velocity,
+            &mut visibility,
+            &mut transform,
+        );
+    }
+}
+
+fn reset_weapons(mut weapon_query: Query<&mut Weapon>, mut slots_query: Query<&mut WeaponSlots>) {
+    for mut weapon in weapon_query.iter_mut() {
+        weapon.make_ready();
+    }
+    for mut slots in slots_query.iter_mut() {
+        slots.active = 0;
     }
 }
 
@@ -268,21 +956,14 @@ fn apply_blade_event(
             &mut Sprite,
             &mut Collider,
             &mut Projectile,
-            &mut Transform,
             &Blade,
         ),
         With<Blade>,
     >,
-    cursor: Res<CursorInfo>,
 ) {
-    for (
-        mut bullet_impulse,
-        mut sprite,
-        mut collider,
-        mut projectile_data,
-        mut bullet_transform,
-        blade,
-    ) in projectile_query.iter_mut()
+    // Also reaches the hidden pooled bullets, so it must leave their transforms alone.
+    for (mut bullet_impulse, mut sprite, mut collider, mut projectile_data, blade) in
+        projectile_query.iter_mut()
     {
         bullet_impulse.torque_impulse += blade.swing_speed;
         match sprite.custom_size {
@@ -290,9 +971,6 @@ fn apply_blade_event(
                 projectile_data.damage += blade.slash_dmg;
                 sprite.custom_size = Some(Vec2::new(size.x * 1.2, size.y));
                 *collider = Collider::capsule_x(size.x * 0.2, 0.5);
-                let direction =
-                    (bullet_transform.translation.xy() - cursor.position().unwrap()).normalize();
-                bullet_transform.translation = direction.extend(1.);
             }
             _ => (),
         }
@@ -300,4 +978,200 @@ fn apply_blade_event(
 }
 
 // TODO: adjust the size of projectiles based on VELOCITY
-// TODO: Blade should have a pierce, if pierce > object armor then it sticks, otherwise ricochets.
+
+/*
+    Minions
+        Drones that flock around the ship and shoot whatever gets close.
+*/
+
+struct MinionPlugin;
+impl Plugin for MinionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<MinionEvent>()
+            .add_systems(Update, spawn_minions.run_if(on_event::<MinionEvent>()))
+            .add_systems(
+                FixedUpdate,
+                (flock_minions, minions_fire.after(flock_minions))
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(OnExit(GameState::GameOver), despawn_minions);
+    }
+}
+
+fn spawn_minions(
+    mut commands: Commands,
+    mut events: EventReader<MinionEvent>,
+    player_query: Query<&Transform, With<Player>>,
+    asset_server: Res<AssetServer>,
+) {
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
+    for MinionEvent(count) in events.read() {
+        for i in 0..*count {
+            // Start them spread around the ship so separation doesn't fling them apart.
+            let angle = i as f32 / *count as f32 * std::f32::consts::TAU;
+            let offset = Vec2::from_angle(angle) * BOID_SEPARATION_RADIUS * 1.5;
+            let mut weapon = Weapon::new(WeaponKind::Blaster, &asset_server);
+            weapon.cooldown = Timer::from_seconds(0.8, TimerMode::Once);
+            weapon.damage = 0.5;
+            commands
+                .spawn(SpriteBundle {
+                    texture: asset_server.load("./ship2.png"),
+                    sprite: Sprite {
+                        color: Color::rgb(0.5, 1.5, 2.0),
+                        custom_size: Some(Vec2::splat(20.)),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(
+                        player_transform.translation + offset.extend(0.),
+                    ),
+                    ..default()
+                })
+                .insert(PlayerBoyd::default())
+                .insert(weapon)
+                .insert(Name::new("Drone"));
+        }
+    }
+}
+
+// Classic boids (cohesion, alignment and separation) plus a leash back to the ship.
+fn flock_minions(
+    mut minions: Query<(Entity, &mut Transform, &mut PlayerBoyd), Without<Player>>,
+    player_query: Query<&Transform, With<Player>>,
+    time: Res<Time>,
+) {
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
+    let player_position = player_transform.translation.xy();
+    let flock: Vec<(Entity, Vec2, Vec2)> = minions
+        .iter()
+        .map(|(entity, transform, boid)| (entity, transform.translation.xy(), boid.velocity))
+        .collect();
+
+    for (entity, mut transform, mut boid) in minions.iter_mut() {
+        let position = transform.translation.xy();
+        let seek = |target: Vec2, boid: &PlayerBoyd| {
+            (target - position).normalize_or_zero() * boid.max_speed - boid.velocity
+        };
+
+        let mut center = Vec2::ZERO;
+        let mut heading = Vec2::ZERO;
+        let mut separation = Vec2::ZERO;
+        let mut neighbors = 0;
+        for (other, other_position, other_velocity) in flock.iter() {
+            let offset = position - *other_position;
+            let distance = offset.length();
+            if *other == entity || distance > BOID_NEIGHBOR_RADIUS {
+                continue;
+            }
+            center += *other_position;
+            heading += *other_velocity;
+            neighbors += 1;
+            if distance > 0. && distance < BOID_SEPARATION_RADIUS {
+                separation += offset / (distance * distance);
+            }
+        }
+
+        let mut steering = Vec2::ZERO;
+        if neighbors > 0 {
+            let cohesion = seek(center / neighbors as f32, &boid);
+            let alignment =
+                (heading / neighbors as f32).normalize_or_zero() * boid.max_speed - boid.velocity;
+            steering += cohesion * BOID_COHESION + alignment * BOID_ALIGNMENT;
+        }
+        if separation != Vec2::ZERO {
+            steering += (separation.normalize() * boid.max_speed - boid.velocity) * BOID_SEPARATION;
+        }
+        // The further a drone strays past the leash, the harder it's pulled back.
+        let leash_distance = position.distance(player_position);
+        if leash_distance > BOID_LEASH_RADIUS {
+            steering +=
+                seek(player_position, &boid) * BOID_LEASH * leash_distance / BOID_LEASH_RADIUS;
+        }
+
+        let acceleration = steering.clamp_length_max(boid.max_force);
+        boid.velocity =
+            (boid.velocity + acceleration * time.delta_seconds()).clamp_length_max(boid.max_speed);
+        transform.translation += (boid.velocity * time.delta_seconds()).extend(0.);
+        if boid.velocity != Vec2::ZERO {
+            transform.rotation =
+                Quat::from_rotation_arc(Vec3::Y, boid.velocity.normalize().extend(0.));
+        }
+    }
+}
+
+type Minion = (With<PlayerBoyd>, Without<Projectile>);
+type MinionTarget = (With<Enemy>, Without<Projectile>, Without<PlayerBoyd>);
+
+fn minions_fire(
+    mut minions: Query<(&Transform, &mut Weapon), Minion>,
+    enemy_query: Query<&Transform, MinionTarget>,
+    mut projectile_query: Query<LaunchableProjectile, (Without<PlayerBoyd>, Without<Enemy>)>,
+    mut pool: ResMut<ProjectilePool>,
+    runtime: Res<GameRuntime>,
+) {
+    for (transform, mut weapon) in minions.iter_mut() {
+        if !weapon.is_ready() {
+            continue;
+        }
+        let position = transform.translation.xy();
+        let target = enemy_query
+            .iter()
+            .map(|enemy_transform| enemy_transform.translation.xy() - position)
+            .filter(|offset| offset.length() < DRONE_RANGE)
+            .min_by(|a, b| a.length_squared().total_cmp(&b.length_squared()));
+        let Some(offset) = target else {
+            continue;
+        };
+        let Some(entity) = pool.acquire() else {
+            return;
+        };
+        let Ok(launch) = projectile_query.get_mut(entity) else {
+            pool.release(entity);
+            continue;
+        };
+        launch_projectile(
+            launch,
+            &weapon,
+            transform.translation,
+            offset.normalize_or_zero(),
+            runtime.0.elapsed_secs(),
+        );
+        weapon.cooldown.reset();
+    }
+}
+
+fn despawn_minions(mut commands: Commands, minions: Query<Entity, With<PlayerBoyd>>) {
+    for entity in minions.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn penetration_thresholds() {
+        assert!(matches!(Penetration::resolve(0., 0.), Penetration::Glance));
+        assert!(matches!(
+            Penetration::resolve(0.5, 0.6),
+            Penetration::Glance
+        ));
+        assert!(matches!(
+            Penetration::resolve(0.6, 0.6),
+            Penetration::Glance
+        ));
+        assert!(matches!(Penetration::resolve(1., 0.6), Penetration::Embed));
+        assert!(matches!(
+            Penetration::resolve(1.2, 0.6),
+            Penetration::PierceThrough
+        ));
+        assert!(matches!(
+            Penetration::resolve(1., 0.),
+            Penetration::PierceThrough
+        ));
+    }
+}
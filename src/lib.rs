@@ -1,7 +1,13 @@
 pub mod constants;
+pub mod controls;
+pub mod effects;
 pub mod game_ui;
 pub mod guns;
 pub mod mobs;
 pub mod parralax;
 pub mod player;
+pub mod pool;
+pub mod status;
+pub mod upgrades;
+pub mod waves;
 pub use constants::*;
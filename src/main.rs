@@ -1,4 +1,6 @@
 use asteroids_bevy::constants::{WH, WW};
+use asteroids_bevy::controls::{ActionState, ControlsPlugin, PlayerAction};
+use asteroids_bevy::effects::EffectsPlugin;
 use asteroids_bevy::game_ui::{GameRuntime, GameState};
 use asteroids_bevy::mobs::MobPlugin;
 use asteroids_bevy::parralax::ParallaxBackgroundPlugin;
@@ -40,6 +42,8 @@ fn main() {
         .add_plugins(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.))
         .add_plugins(RapierDebugRenderPlugin::default())
         .add_plugins(HanabiPlugin)
+        .add_plugins(ControlsPlugin)
+        .add_plugins(EffectsPlugin)
         .add_plugins(PlayerPlugin)
         .add_plugins(MobPlugin)
         .add_plugins(ParallaxBackgroundPlugin)
@@ -232,9 +236,9 @@ fn fps_text_update_system(
     }
 }
 
-/// Toggle the FPS counter when pressing F12
-fn fps_counter_showhide(mut q: Query<&mut Visibility, With<FpsRoot>>, kbd: Res<Input<KeyCode>>) {
-    if kbd.just_pressed(KeyCode::F12) {
+/// Toggle the FPS counter on PlayerAction::ToggleFps (F12 by default)
+fn fps_counter_showhide(mut q: Query<&mut Visibility, With<FpsRoot>>, actions: Res<ActionState>) {
+    if actions.just_pressed(PlayerAction::ToggleFps) {
         let mut vis = q.single_mut();
         *vis = match *vis {
             Visibility::Hidden => Visibility::Visible,
@@ -263,15 +267,17 @@ fn pause_system(
     mut stopwatch: ResMut<GameRuntime>,
     curr_gamestate: Res<State<GameState>>,
     mut gamestate: ResMut<NextState<GameState>>,
-    keyboard_input: Res<Input<KeyCode>>,
+    actions: Res<ActionState>,
 ) {
     let curr_state = curr_gamestate.get();
-    if keyboard_input.just_pressed(KeyCode::Q) {
+    if actions.just_pressed(PlayerAction::Pause) {
         match curr_state {
             GameState::Paused => {
                 stopwatch.0.unpause();
                 gamestate.set(GameState::Playing)
             }
+            // Can't pause your way out of the menus.
+            GameState::GameOver | GameState::StartMenu | GameState::Settings => (),
             _ => {
                 stopwatch.0.paused();
                 gamestate.set(GameState::Paused)
@@ -1,29 +1,99 @@
 use crate::constants::BASE_EXP_PULL;
+use crate::effects::{EffectKind, SpawnEffect};
 use crate::game_ui::{GameRuntime, GameState};
-use crate::guns::Projectile;
+use crate::guns::{
+    recycle_projectile, Blade, Burn, Emp, Penetration, Piercing, Projectile, ProjectilePool,
+    Tracking,
+};
 use crate::player::{LevelUpEvent, Player, Warpable, WindowSize};
+use crate::pool::Pool;
+use crate::status::{ignite, release_embedded, stun, Burning, Embedded, Stunned};
+use crate::waves::{WaveTable, WaveTableLoader, WAVES_PATH};
 
+use bevy::asset::LoadState;
 use bevy::audio::Volume;
+use bevy::ecs::system::EntityCommands;
 use bevy::prelude::*;
+use bevy::utils::HashMap;
 use bevy_rapier2d::prelude::*;
 use rand::{thread_rng, Rng};
 
+const FRAGMENT_SCALE: f32 = 0.6;
+const FRAGMENT_HEALTH_SCALE: f32 = 0.5;
+const FRAGMENT_SPREAD: f32 = 120.;
+const SHARD_POOL_SIZE: usize = 60;
+const SHARD_POOL_GROWTH: usize = 20;
+
 #[derive(Resource)]
 struct CollisionSound(Handle<AudioSource>);
 #[derive(Component)]
 pub struct Enemy {
     pub health: f32,
+    pub max_health: f32,
     pub collision_damage: f32,
+    // Enemies above tier 0 break into smaller enemies of tier - 1 when destroyed.
+    pub tier: u8,
+    // Shots need more pierce than this to do any damage, see Penetration::resolve.
+    pub armor: f32,
+}
+/// Soaks up all bullet damage until an EMP strips it.
+#[derive(Component)]
+pub struct Shield {
+    pub strength: f32,
+}
+
+impl Shield {
+    pub fn is_up(&self) -> bool {
+        self.strength > 0.
+    }
 }
 #[derive(Component)]
 pub struct ExperienceShard(f32);
+pub type ShardPool = Pool<ExperienceShard>;
+/// Enemies destroyed this run.
+#[derive(Resource, Default)]
+pub struct KillCount(pub u32);
+
+/// Everything needed to spawn one kind of enemy.
+#[derive(Clone, Debug)]
+pub struct EnemyArchetype {
+    pub texture: Handle<Image>,
+    pub size: Vec2,
+    pub color: Color,
+    pub collider: Collider,
+    pub mass: f32,
+    pub health: f32,
+    pub collision_damage: f32,
+    pub memberships: Group,
+    pub filters: Group,
+    pub tier: u8,
+    pub armor: f32,
+    // 0 for enemies without a Shield.
+    pub shield: f32,
+}
+
+/// Enemy archetypes by name, as referenced from the wave table.
+#[derive(Resource, Default)]
+pub struct EnemyArchetypes(pub HashMap<String, EnemyArchetype>);
+
+impl EnemyArchetypes {
+    pub fn get(&self, name: &str) -> Option<&EnemyArchetype> {
+        self.0.get(name)
+    }
+}
 
 pub struct MobPlugin;
 
 impl Plugin for MobPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(EnemyWavePlugin)
-            .add_systems(Startup, setup)
+            .add_plugins(StalkerEnemyPlugin)
+            .add_plugins(TurretEnemyPlugin)
+            .init_resource::<KillCount>()
+            .insert_resource(ShardPool::new(SHARD_POOL_GROWTH))
+            .add_systems(Startup, (setup, setup_shard_pool))
+            .add_systems(Update, grow_shard_pool.run_if(in_state(GameState::Playing)))
+            .add_systems(OnExit(GameState::GameOver), despawn_mobs)
             .add_systems(
                 PostUpdate,
                 exp_pull_system.run_if(in_state(GameState::Playing)),
@@ -31,8 +101,16 @@ impl Plugin for MobPlugin {
             .add_systems(
                 PostUpdate,
                 kill_on_contact
+                    // Projectile::incoming has to still be from before this step.
+                    .after(PhysicsSet::Writeback)
                     .run_if(on_event::<CollisionEvent>())
                     .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                PostUpdate,
+                destroy_dead_enemies
+                    .after(kill_on_contact)
+                    .run_if(in_state(GameState::Playing)),
             );
     }
 }
@@ -41,31 +119,210 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
     let bullet_collision_sound = asset_server.load("Sounds/hitmarker_2.ogg");
     // let bullet_collision_sound = asset_server.load("Sounds/breakout_collision.ogg");
     commands.insert_resource(CollisionSound(bullet_collision_sound));
+    commands.insert_resource(enemy_archetypes(|path| asset_server.load(path)));
 }
 
+// Builds the registry, `load_texture` turns an asset path into a handle.
+fn enemy_archetypes(load_texture: impl Fn(&'static str) -> Handle<Image>) -> EnemyArchetypes {
+    // Bigger asteroids split more times before they are gone.
+    let asteroid = |texture: &'static str, size: f32, radius: f32, tier: u8| EnemyArchetype {
+        texture: load_texture(texture),
+        size: Vec2::splat(size),
+        color: Color::rgb(1.2, 1.2, 1.2),
+        collider: Collider::ball(radius),
+        mass: 100.,
+        health: 100.,
+        collision_damage: 10.,
+        memberships: Group::GROUP_3,
+        filters: Group::GROUP_1 | Group::GROUP_2,
+        tier,
+        armor: 0.,
+        shield: 0.,
+    };
+    let mut archetypes = EnemyArchetypes::default();
+    archetypes.0.insert(
+        "asteroid_small".into(),
+        asteroid("Asteroids/A3__00004.png", 150., 30., 0),
+    );
+    archetypes.0.insert(
+        "asteroid_medium".into(),
+        asteroid("Asteroids/A4__00001.png", 200., 40., 1),
+    );
+    archetypes.0.insert(
+        "asteroid_large".into(),
+        asteroid("Asteroids/A1__00000.png", 250., 50., 2),
+    );
+    archetypes.0.insert(
+        "asteroid_armored".into(),
+        EnemyArchetype {
+            color: Color::rgb(0.8, 0.9, 1.4),
+            health: 150.,
+            // Blaster shots stick, spread pellets ricochet and blades go through.
+            armor: 0.6,
+            ..asteroid("Asteroids/A4__00001.png", 180., 36., 0)
+        },
+    );
+    archetypes.0.insert(
+        "asteroid_shielded".into(),
+        EnemyArchetype {
+            color: Color::rgb(0.6, 1.4, 1.6),
+            // Shrugs off everything until an EMP cracks it.
+            shield: 30.,
+            ..asteroid("Asteroids/A3__00004.png", 160., 32., 0)
+        },
+    );
+    archetypes.0.insert(
+        "stalker".into(),
+        EnemyArchetype {
+            texture: load_texture("ship1.png"),
+            size: Vec2::splat(40.),
+            color: Color::rgb(1.2, 1.2, 1.2),
+            collider: Collider::ball(18.),
+            mass: 10.,
+            health: 30.,
+            collision_damage: 20.,
+            memberships: Group::GROUP_3,
+            filters: Group::GROUP_1 | Group::GROUP_2,
+            tier: 0,
+            armor: 0.,
+            shield: 0.,
+        },
+    );
+    archetypes.0.insert(
+        "turret".into(),
+        EnemyArchetype {
+            texture: load_texture("ship1.png"),
+            size: Vec2::splat(70.),
+            color: Color::rgb(2.0, 0.4, 0.4),
+            collider: Collider::ball(30.),
+            mass: 500.,
+            health: 200.,
+            collision_damage: 10.,
+            memberships: Group::GROUP_3,
+            filters: Group::GROUP_1 | Group::GROUP_2,
+            tier: 0,
+            armor: 0.,
+            shield: 0.,
+        },
+    );
+    archetypes
+}
+
+/// Spawns an enemy at rest, callers add their own ExternalImpulse to set it moving.
+pub fn spawn_enemy<'w, 's, 'a>(
+    commands: &'a mut Commands<'w, 's>,
+    archetype: &EnemyArchetype,
+    transform: Transform,
+) -> EntityCommands<'w, 's, 'a> {
+    let mut enemy = commands.spawn(SpriteBundle {
+        texture: archetype.texture.clone(),
+        sprite: Sprite {
+            color: archetype.color,
+            custom_size: Some(archetype.size),
+            ..default()
+        },
+        transform,
+        ..default()
+    });
+    enemy
+        .insert(Enemy {
+            health: archetype.health,
+            max_health: archetype.health,
+            collision_damage: archetype.collision_damage,
+            tier: archetype.tier,
+            armor: archetype.armor,
+        })
+        .insert(Warpable)
+        .insert(ExternalImpulse::default())
+        .insert(archetype.collider.clone())
+        .insert(RigidBody::Dynamic)
+        .insert(AdditionalMassProperties::Mass(archetype.mass))
+        .insert(GravityScale(0.))
+        .insert(Velocity::zero())
+        .insert(CollisionGroups::new(
+            archetype.memberships,
+            archetype.filters,
+        ))
+        .insert(SolverGroups::new(archetype.memberships, archetype.filters))
+        .insert(ActiveEvents::COLLISION_EVENTS);
+    if archetype.shield > 0. {
+        enemy.insert(Shield {
+            strength: archetype.shield,
+        });
+    }
+    enemy
+}
+
+// A bullet as kill_on_contact sees it, along with the upgrades it carries.
+type HitBullet = (
+    Entity,
+    &'static mut Velocity,
+    &'static mut Projectile,
+    &'static mut Transform,
+    &'static mut Visibility,
+    Option<&'static Tracking>,
+    Option<&'static Burn>,
+    Option<&'static Emp>,
+    &'static Blade,
+    Option<&'static Piercing>,
+);
+// An enemy as kill_on_contact sees it, along with any status effects to update.
+type HitEnemy = (
+    Entity,
+    &'static Transform,
+    &'static mut Enemy,
+    Option<&'static mut Burning>,
+    Option<&'static mut Shield>,
+    Option<&'static mut Stunned>,
+);
+
+#[allow(clippy::too_many_arguments)]
 fn kill_on_contact(
     mut commands: Commands,
-    mut bullets: Query<(Entity, &mut Velocity, &Projectile, &mut Transform), With<Projectile>>,
-    mut enemies: Query<(Entity, &mut Transform, &mut Enemy), (With<Enemy>, Without<Projectile>)>,
+    mut bullets: Query<HitBullet, With<Projectile>>,
+    mut enemies: Query<HitEnemy, (With<Enemy>, Without<Projectile>)>,
     mut contact_events: EventReader<CollisionEvent>,
     sound: Res<CollisionSound>,
     asset_server: Res<AssetServer>,
+    rapier_context: Res<RapierContext>,
+    mut projectile_pool: ResMut<ProjectilePool>,
+    mut shard_pool: ResMut<ShardPool>,
+    mut effects: EventWriter<SpawnEffect>,
 ) {
     for contact_event in contact_events.read() {
         if let CollisionEvent::Started(entity1, entity2, _) = contact_event {
-            let bullet_entity = bullets.iter_mut().find(|(bullet_entity, _, _, _)| {
+            let bullet_entity = bullets.iter_mut().find(|(bullet_entity, ..)| {
                 *bullet_entity == *entity1 || *bullet_entity == *entity2
             });
 
-            let enemy_entity = enemies.iter_mut().find(|(enemy_entity, _, _)| {
-                *enemy_entity == *entity1 || *enemy_entity == *entity2
-            });
+            let enemy_entity = enemies
+                .iter_mut()
+                .find(|(enemy_entity, ..)| *enemy_entity == *entity1 || *enemy_entity == *entity2);
 
             if let (
-                Some((_, mut bullet_velocity, projectile_data, mut bullet_transform)),
-                Some((enemy_entity, enemy_transform, mut enemy_data)),
+                Some((
+                    bullet_entity,
+                    mut bullet_velocity,
+                    mut projectile_data,
+                    mut bullet_transform,
+                    mut bullet_visibility,
+                    tracking,
+                    burn,
+                    emp,
+                    blade,
+                    piercing,
+                )),
+                Some((enemy_entity, enemy_transform, mut enemy_data, burning, mut shield, stunned)),
             ) = (bullet_entity, enemy_entity)
             {
+                // Pooled bullets keep their colliders while hidden.
+                if *bullet_visibility == Visibility::Hidden {
+                    continue;
+                }
+                // Still on its way through this one.
+                if piercing.is_some_and(|piercing| piercing.through == enemy_entity) {
+                    continue;
+                }
                 let x_rand = thread_rng().gen_range(-100..100) as f32;
                 let y_rand = thread_rng().gen_range(-100..100) as f32;
                 let shard_velocity = Vec2::new(x_rand, y_rand);
@@ -83,67 +340,306 @@ fn kill_on_contact(
                     // settings: PlaybackSettings::DESPAWN,
                     settings: audio_settings,
                 });
+                effects.send(SpawnEffect {
+                    kind: EffectKind::Spark,
+                    translation: bullet_transform.translation,
+                });
+
+                if let Some(emp) = emp {
+                    stun(&mut commands, enemy_entity, stunned, emp.stun_length);
+                }
+
+                // The solver has already pushed the bullet back, so work from what it had before.
+                let incoming = if projectile_data.incoming == Vec2::ZERO {
+                    bullet_velocity.linvel
+                } else {
+                    projectile_data.incoming
+                };
+
+                let pierce = blade.pierce + projectile_data.weapon_pierce;
+                let penetration = resolve_hit(pierce, enemy_data.armor, shield.as_deref_mut(), emp);
+                let armor_modifier = match penetration {
+                    Penetration::Glance => {
+                        projectile_data.bounces += 1;
+                        if projectile_data.bounces > projectile_data.max_bounces {
+                            recycle_projectile(
+                                &mut projectile_pool,
+                                bullet_entity,
+                                &mut bullet_velocity,
+                                &mut bullet_visibility,
+                                &mut bullet_transform,
+                            );
+                            continue;
+                        }
+                        // Apply ricochet effect to bullet
+                        let normal = contact_normal(&rapier_context, bullet_entity, enemy_entity)
+                            .unwrap_or_else(|| {
+                                (bullet_transform.translation - enemy_transform.translation)
+                                    .xy()
+                                    .normalize_or_zero()
+                            });
+                        bullet_velocity.linvel =
+                            ricochet(incoming, normal, projectile_data.restitution);
+                        let angle = bullet_velocity.linvel.y.atan2(bullet_velocity.linvel.x);
+                        bullet_transform.rotation = Quat::from_rotation_z(angle);
+                        continue;
+                    }
+                    Penetration::Embed => {
+                        commands
+                            .entity(bullet_entity)
+                            .insert(Embedded::new(blade.bleed))
+                            .insert(RigidBodyDisabled)
+                            .insert(ColliderDisabled)
+                            .set_parent_in_place(enemy_entity);
+                        1.
+                    }
+                    Penetration::PierceThrough => {
+                        // Keep going as if nothing was there rather than taking the solver's bounce.
+                        bullet_velocity.linvel = incoming;
+                        commands
+                            .entity(bullet_entity)
+                            .insert(Sensor)
+                            .insert(Piercing::new(enemy_entity));
+                        1. - enemy_data.armor / pierce
+                    }
+                };
 
-                // Apply ricochet effect to bullet
-                let bul_vel = bullet_velocity.linvel.dot(Vec2::new(0.0, 1.0)) * Vec2::new(0.0, 1.0);
-                bullet_velocity.linvel -= 2.0 * bul_vel;
-                let angle = bullet_velocity.linvel.y.atan2(bullet_velocity.linvel.x);
-                bullet_transform.rotation = Quat::from_rotation_z(angle);
-
-                let enemy_loc = enemy_transform.clone();
-                // Despawn enemy
-                // TODO replace with spawn pool
-                commands
-                    .spawn(SpriteBundle {
-                        // texture: asset_server.load("./xp1.png"),
-                        texture: asset_server.load("./xp2.png"),
-                        sprite: Sprite {
-                            // color: Color::rgb(0.25, 0.25, 0.75),
-                            color: Color::rgb(1.2, 1.2, 1.2),
-                            custom_size: Some(Vec2::splat(20.)),
-                            ..default()
-                        },
-                        // transform: Transform::from_translation(Vec3::new(100., 400., 2.)),
-                        transform: enemy_loc,
-                        ..default()
-                    })
-                    .insert(ExperienceShard(10.))
-                    .insert(ExternalImpulse {
-                        impulse: Vec2::ZERO,
-                        torque_impulse: 5.00,
-                    })
-                    .insert(Warpable)
-                    .insert(RigidBody::Dynamic)
-                    .insert(Damping {
-                        linear_damping: 0.2,
-                        angular_damping: 0.0,
-                    })
-                    .insert(Sensor)
-                    .insert(AdditionalMassProperties::Mass(1.0))
-                    .insert(GravityScale(0.))
-                    .insert(Velocity::linear(shard_velocity));
-
-                enemy_data.health -= projectile_data.damage;
-                if enemy_data.health < 0. {
-                    info!("Deleting entity. {:?}", enemy_entity);
-                    commands.entity(enemy_entity).despawn_recursive();
+                let enemy_loc = *enemy_transform;
+                // Only the smallest asteroids drop experience, larger ones split instead.
+                if enemy_data.tier == 0 {
+                    let shard =
+                        shard_pool.acquire_or_spawn(|| spawn_shard(&mut commands, &asset_server));
+                    commands
+                        .entity(shard)
+                        .remove::<RigidBodyDisabled>()
+                        .insert(enemy_loc)
+                        .insert(Visibility::Visible)
+                        .insert(ExternalImpulse {
+                            impulse: Vec2::ZERO,
+                            torque_impulse: 5.00,
+                        })
+                        .insert(Warpable)
+                        .insert(Velocity::linear(shard_velocity));
                 }
+
+                if let Some(burn) = burn {
+                    ignite(&mut commands, enemy_entity, burning, burn.dot);
+                }
+
+                // Homing trades away some damage.
+                let damage = match tracking {
+                    Some(tracking) => projectile_data.damage * (1. - tracking.damage_modifier),
+                    None => projectile_data.damage,
+                };
+                enemy_data.health -= damage * projectile_data.weapon_damage * armor_modifier;
+            }
+        }
+    }
+}
+
+// EMP damage goes into the shield first, whatever is still shielded glances off.
+fn resolve_hit(
+    pierce: f32,
+    armor: f32,
+    shield: Option<&mut Shield>,
+    emp: Option<&Emp>,
+) -> Penetration {
+    if let Some(shield) = shield {
+        if let Some(emp) = emp {
+            shield.strength = (shield.strength - emp.shield_damage).max(0.);
+        }
+        if shield.is_up() {
+            return Penetration::Glance;
+        }
+    }
+    Penetration::resolve(pierce, armor)
+}
+
+// World space normal pointing from the enemy out towards the bullet, while Rapier has them touching.
+fn contact_normal(rapier_context: &RapierContext, bullet: Entity, enemy: Entity) -> Option<Vec2> {
+    let pair = rapier_context.contact_pair(bullet, enemy)?;
+    let manifold = pair
+        .manifolds()
+        .find(|manifold| manifold.num_points() > 0)?;
+    // Manifold normals point from collider1 to collider2.
+    let normal = if pair.collider1() == enemy {
+        manifold.normal()
+    } else {
+        -manifold.normal()
+    };
+    Some(normal.normalize_or_zero()).filter(|normal| *normal != Vec2::ZERO)
+}
+
+// Reflects `velocity` off a surface, `restitution` is how much of the speed into it comes back out.
+fn ricochet(velocity: Vec2, normal: Vec2, restitution: f32) -> Vec2 {
+    let into_surface = velocity.dot(normal);
+    if into_surface >= 0. {
+        return velocity;
+    }
+    velocity - (1. + restitution) * into_surface * normal
+}
+
+// Everything needed to despawn an enemy and build its fragments.
+type DyingEnemy = (
+    Entity,
+    &'static Transform,
+    &'static Enemy,
+    &'static Velocity,
+    &'static Sprite,
+    &'static Handle<Image>,
+    &'static Collider,
+    &'static CollisionGroups,
+    &'static AdditionalMassProperties,
+    Option<&'static Burning>,
+    Option<&'static Children>,
+);
+
+// Removes enemies that ran out of health, whether from bullets or damage over time.
+fn destroy_dead_enemies(
+    mut commands: Commands,
+    enemies: Query<DyingEnemy>,
+    embedded: Query<(), With<Embedded>>,
+    mut kills: ResMut<KillCount>,
+    mut projectile_pool: ResMut<ProjectilePool>,
+    mut effects: EventWriter<SpawnEffect>,
+) {
+    for (
+        enemy_entity,
+        enemy_transform,
+        enemy_data,
+        enemy_velocity,
+        sprite,
+        texture,
+        collider,
+        groups,
+        mass,
+        burning,
+        children,
+    ) in enemies.iter()
+    {
+        if enemy_data.health >= 0. {
+            continue;
+        }
+        // Hand stuck bullets back to the pool before they go down with the enemy.
+        for &child in children.into_iter().flatten() {
+            if embedded.contains(child) {
+                release_embedded(&mut commands, &mut projectile_pool, child);
             }
         }
+        info!("Deleting entity. {:?}", enemy_entity);
+        commands.entity(enemy_entity).despawn_recursive();
+        kills.0 += 1;
+        effects.send(SpawnEffect {
+            kind: EffectKind::Explosion,
+            translation: enemy_transform.translation,
+        });
+
+        if enemy_data.tier > 0 {
+            let mut fragment_collider = collider.clone();
+            fragment_collider.set_scale(collider.scale() * FRAGMENT_SCALE, 10);
+            let parent_mass = match mass {
+                AdditionalMassProperties::Mass(mass) => *mass,
+                AdditionalMassProperties::MassProperties(props) => props.mass,
+            };
+            let fragment = EnemyArchetype {
+                texture: texture.clone(),
+                size: sprite.custom_size.unwrap_or(Vec2::ONE) * FRAGMENT_SCALE,
+                // Fragments shouldn't keep the burn tint without the burn.
+                color: burning
+                    .map_or(sprite.color, |burning| burning.original_color)
+                    .with_a(1.),
+                collider: fragment_collider,
+                mass: parent_mass * FRAGMENT_SCALE,
+                health: enemy_data.max_health * FRAGMENT_HEALTH_SCALE,
+                collision_damage: enemy_data.collision_damage,
+                memberships: groups.memberships,
+                filters: groups.filters,
+                tier: enemy_data.tier - 1,
+                armor: enemy_data.armor,
+                shield: 0.,
+            };
+            spawn_fragments(
+                &mut commands,
+                &fragment,
+                *enemy_transform,
+                enemy_velocity.linvel,
+            );
+        }
     }
 }
 
+// Breaks an enemy into 2-3 pieces that fly apart from where it died.
+fn spawn_fragments(
+    commands: &mut Commands,
+    fragment: &EnemyArchetype,
+    parent_transform: Transform,
+    parent_velocity: Vec2,
+) {
+    let mut rng = thread_rng();
+    let count = rng.gen_range(2..=3);
+    for i in 0..count {
+        let angle = std::f32::consts::TAU * i as f32 / count as f32 + rng.gen_range(-0.4..0.4);
+        let direction = Vec2::from_angle(angle);
+        let speed = rng.gen_range(FRAGMENT_SPREAD * 0.5..FRAGMENT_SPREAD);
+        let mut transform = parent_transform;
+        transform.translation += (direction * fragment.size.x * 0.5).extend(0.);
+        spawn_enemy(commands, fragment, transform)
+            .insert(Velocity::linear(parent_velocity + direction * speed));
+    }
+}
+
+type Mob = Or<(With<Enemy>, With<EnemyProjectile>)>;
+
+// Clears the field for a new run.
+fn despawn_mobs(
+    mut commands: Commands,
+    mobs: Query<Entity, Mob>,
+    shards: Query<Entity, With<ExperienceShard>>,
+    embedded: Query<Entity, With<Embedded>>,
+    mut kills: ResMut<KillCount>,
+    mut projectile_pool: ResMut<ProjectilePool>,
+    mut shard_pool: ResMut<ShardPool>,
+) {
+    for entity in embedded.iter() {
+        release_embedded(&mut commands, &mut projectile_pool, entity);
+    }
+    for entity in shards.iter() {
+        release_shard(&mut commands, &mut shard_pool, entity);
+    }
+    for entity in mobs.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    kills.0 = 0;
+}
+
 fn exp_pull_system(
     mut commands: Commands,
-    mut shards: Query<(Entity, &Transform, &mut Velocity, &ExperienceShard), With<ExperienceShard>>,
+    mut shards: Query<
+        (
+            Entity,
+            &Transform,
+            &mut Velocity,
+            &Visibility,
+            &ExperienceShard,
+        ),
+        With<ExperienceShard>,
+    >,
     mut player: Query<(&Transform, &mut Player), (With<Player>, Without<ExperienceShard>)>,
     mut event_writer: EventWriter<LevelUpEvent>,
     mut gamestate: ResMut<NextState<GameState>>,
+    mut shard_pool: ResMut<ShardPool>,
+    mut effects: EventWriter<SpawnEffect>,
 ) {
     let exp_pull_range: f32 = BASE_EXP_PULL;
     let exp_absorb_range: f32 = 40.;
     let (player_transform, mut player_data) = player.single_mut();
-    for (shard_entity, shard_transform, mut shard_velocity, shard_data) in shards.iter_mut() {
+    for (shard_entity, shard_transform, mut shard_velocity, visibility, shard_data) in
+        shards.iter_mut()
+    {
+        // Still waiting in the pool.
+        if *visibility == Visibility::Hidden {
+            continue;
+        }
         let distance = player_transform
             .translation
             .distance(shard_transform.translation);
@@ -161,11 +657,89 @@ fn exp_pull_system(
                 player_data.level += 1;
                 player_data.exp_max = player_data.exp_max * 1.2;
             }
-            commands.entity(shard_entity).despawn_recursive();
+            effects.send(SpawnEffect {
+                kind: EffectKind::ShardPickup,
+                translation: shard_transform.translation,
+            });
+            release_shard(&mut commands, &mut shard_pool, shard_entity);
         }
     }
 }
 
+fn setup_shard_pool(
+    mut commands: Commands,
+    mut shard_pool: ResMut<ShardPool>,
+    asset_server: Res<AssetServer>,
+) {
+    for _ in 0..SHARD_POOL_SIZE {
+        let shard = spawn_shard(&mut commands, &asset_server);
+        shard_pool.add(shard);
+    }
+}
+
+fn grow_shard_pool(
+    mut commands: Commands,
+    mut shard_pool: ResMut<ShardPool>,
+    asset_server: Res<AssetServer>,
+) {
+    if !shard_pool.needs_growth() {
+        return;
+    }
+    for _ in 0..shard_pool.grow_by {
+        let shard = spawn_shard(&mut commands, &asset_server);
+        shard_pool.add(shard);
+    }
+    debug!(
+        "Grew shard pool to {} ({} in use)",
+        shard_pool.len(),
+        shard_pool.in_use()
+    );
+}
+
+// A hidden, frozen shard waiting in the pool.
+fn spawn_shard(commands: &mut Commands, asset_server: &AssetServer) -> Entity {
+    commands
+        .spawn(SpriteBundle {
+            // texture: asset_server.load("./xp1.png"),
+            texture: asset_server.load("./xp2.png"),
+            sprite: Sprite {
+                // color: Color::rgb(0.25, 0.25, 0.75),
+                color: Color::rgb(1.2, 1.2, 1.2),
+                custom_size: Some(Vec2::splat(20.)),
+                ..default()
+            },
+            transform: Transform::from_translation(Vec3::new(10000., 100000., -1.)),
+            visibility: Visibility::Hidden,
+            ..default()
+        })
+        .insert(ExperienceShard(10.))
+        .insert(ExternalImpulse::default())
+        .insert(RigidBody::Dynamic)
+        .insert(RigidBodyDisabled)
+        .insert(Damping {
+            linear_damping: 0.2,
+            angular_damping: 0.0,
+        })
+        .insert(Sensor)
+        .insert(AdditionalMassProperties::Mass(1.0))
+        .insert(GravityScale(0.))
+        .insert(Velocity::zero())
+        .id()
+}
+
+fn release_shard(commands: &mut Commands, shard_pool: &mut ShardPool, shard: Entity) {
+    if !shard_pool.release(shard) {
+        return;
+    }
+    commands
+        .entity(shard)
+        .remove::<Warpable>()
+        .insert(RigidBodyDisabled)
+        .insert(Visibility::Hidden)
+        .insert(Velocity::zero())
+        .insert(Transform::from_translation(Vec3::new(10000., 100000., -1.)));
+}
+
 /*
     Mob plugins
         Asteroid field
@@ -174,201 +748,473 @@ fn exp_pull_system(
 */
 
 #[derive(Resource)]
-struct CurrentWave(i32);
+struct CurrentWave(usize);
+#[derive(Resource)]
+struct WaveTableHandle(Handle<WaveTable>);
+#[derive(Resource, Default)]
+struct FallbackWaves(WaveTable);
 
 struct EnemyWavePlugin;
 impl Plugin for EnemyWavePlugin {
     fn build(&self, app: &mut App) {
-        // TODO: Spawns a random set of enemies every # minutes.
-        app.insert_resource(CurrentWave(1))
+        app.init_asset::<WaveTable>()
+            .init_asset_loader::<WaveTableLoader>()
+            .init_resource::<FallbackWaves>()
+            .insert_resource(CurrentWave(0))
+            .add_systems(Startup, load_wave_table)
+            .add_systems(OnExit(GameState::GameOver), reset_waves)
+            .add_systems(Update, log_wave_table_changes)
             .add_systems(Update, spawn_wave.run_if(in_state(GameState::Playing)));
     }
 }
 
+fn load_wave_table(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(WaveTableHandle(asset_server.load(WAVES_PATH)));
+}
+
+fn reset_waves(mut wave: ResMut<CurrentWave>) {
+    wave.0 = 0;
+}
+
+fn log_wave_table_changes(
+    mut asset_events: EventReader<AssetEvent<WaveTable>>,
+    wave_tables: Res<Assets<WaveTable>>,
+) {
+    for event in asset_events.read() {
+        match event {
+            AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id } => {
+                if let Some(table) = wave_tables.get(*id) {
+                    info!("Loaded {} with {} waves.", WAVES_PATH, table.waves.len());
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn spawn_wave(
     mut commands: Commands,
     player: Query<&Transform, (With<Player>, Without<ExperienceShard>)>,
     mut wave: ResMut<CurrentWave>,
     asset_server: Res<AssetServer>,
+    wave_tables: Res<Assets<WaveTable>>,
+    wave_handle: Res<WaveTableHandle>,
+    fallback: Res<FallbackWaves>,
+    archetypes: Res<EnemyArchetypes>,
     time: Res<GameRuntime>,
     win_size: Res<WindowSize>,
 ) {
+    let table = match wave_tables.get(&wave_handle.0) {
+        Some(table) => table,
+        None => match asset_server.get_load_state(&wave_handle.0) {
+            Some(LoadState::Failed) | None => &fallback.0,
+            // Still loading, don't spawn from the fallback in the meantime.
+            _ => return,
+        },
+    };
+    let Some(next_wave) = table.waves.get(wave.0) else {
+        return;
+    };
+    if time.0.elapsed_secs() < next_wave.trigger_secs {
+        return;
+    }
+    wave.0 += 1;
+
+    let Some(archetype) = archetypes.get(&next_wave.archetype) else {
+        warn!(
+            "Unknown enemy archetype {:?}, skipping wave.",
+            next_wave.archetype
+        );
+        return;
+    };
+
+    debug!("Wave {} spawned.", wave.0);
     let player_transform = player.single();
     let mut rng = rand::thread_rng();
-    let elapsed_seconds = time.0.elapsed_secs();
-    let elapsed_minutes = elapsed_seconds / 60.;
-
-    if elapsed_seconds > 10. && wave.0 == 1 {
-        debug!("Wave 1 spawned.");
-        for _ in 0..40 {
-            let random_x = rng.gen_range(-1000. ..1000.) as f32;
-            let random_y = rng.gen_range(-1000. ..1000.) as f32;
-            let direction = player_transform.translation.xy() - Vec2::new(random_x, random_y);
-            let left_or_right = if rng.gen_bool(0.5) {
-                let left_pad = win_size.left_wall;
-                rng.gen_range(left_pad * 1.2..left_pad) as f32
-            } else {
-                let right_pad = win_size.right_wall;
-                rng.gen_range(right_pad..right_pad * 1.2) as f32
-            };
-            commands
-                .spawn(SpriteBundle {
-                    texture: asset_server.load("Asteroids/A3__00004.png"),
-                    sprite: Sprite {
-                        // color: Color::rgb(0.25, 0.25, 0.75),
-                        color: Color::rgb(1.2, 1.2, 1.2),
-                        custom_size: Some(Vec2::new(150.0, 150.0)),
-                        ..default()
-                    },
-                    // transform: Transform::from_translation(Vec3::new(-200., -400., 2.)),
-                    transform: Transform::from_translation(Vec3::new(left_or_right, random_y, 2.)),
-                    ..default()
-                })
-                .insert(Enemy {
-                    health: 100.,
-                    collision_damage: 10.,
-                })
-                .insert(Warpable)
-                .insert(ExternalImpulse {
-                    impulse: direction * 0.02,
-                    torque_impulse: 0.02,
-                })
-                .insert(Collider::ball(30.0))
-                .insert(RigidBody::Dynamic)
-                .insert(AdditionalMassProperties::Mass(100.0))
-                .insert(GravityScale(0.))
-                .insert(Velocity::linear(Vec2::new(0., 0.)))
-                .insert(CollisionGroups::new(
-                    Group::GROUP_3,
-                    Group::GROUP_1 | Group::GROUP_2,
-                ))
-                .insert(SolverGroups::new(
-                    Group::GROUP_3,
-                    Group::GROUP_1 | Group::GROUP_2,
-                ))
-                .insert(Sleeping {
-                    sleeping: true,
-                    ..default()
-                })
-                .insert(ActiveEvents::COLLISION_EVENTS);
+    let ring = next_wave.spawn_ring;
+    for _ in 0..next_wave.count {
+        let random_x = rng.gen_range(-1000. ..1000.) as f32;
+        let random_y = rng.gen_range(-ring.height..=ring.height);
+        let direction = player_transform.translation.xy() - Vec2::new(random_x, random_y);
+        let left_or_right = if rng.gen_bool(0.5) {
+            let left_pad = win_size.left_wall;
+            rng.gen_range(left_pad * ring.outer..=left_pad * ring.inner)
+        } else {
+            let right_pad = win_size.right_wall;
+            rng.gen_range(right_pad * ring.inner..=right_pad * ring.outer)
+        };
+        let transform = Transform::from_translation(Vec3::new(left_or_right, random_y, 2.));
+        spawn_enemy(&mut commands, archetype, transform).insert(ExternalImpulse {
+            impulse: direction * next_wave.impulse.linear,
+            torque_impulse: next_wave.impulse.torque,
+        });
+    }
+}
+#[derive(Component)]
+pub struct Stalker {
+    pub max_speed: f32,
+    pub max_force: f32,
+    pub separation_radius: f32,
+}
+#[derive(Resource)]
+struct StalkerSpawner {
+    next_spawn_secs: f32,
+    interval_secs: f32,
+}
+
+impl Default for StalkerSpawner {
+    fn default() -> Self {
+        StalkerSpawner {
+            next_spawn_secs: 20.,
+            interval_secs: 5.,
         }
+    }
+}
 
-        wave.0 += 1;
-    } else if elapsed_minutes > 1. && wave.0 == 2 {
-        for _ in 0..60 {
-            let random_x = rng.gen_range(-1000. ..1000.) as f32;
-            let random_y = rng.gen_range(-1000. ..1000.) as f32;
-            let direction = player_transform.translation.xy() - Vec2::new(random_x, random_y);
-            let left_or_right = if rng.gen_bool(0.5) {
-                let left_pad = win_size.left_wall;
-                rng.gen_range(left_pad * 1.2..left_pad) as f32
-            } else {
-                let right_pad = win_size.right_wall;
-                rng.gen_range(right_pad..right_pad * 1.2) as f32
-            };
-            commands
-                .spawn(SpriteBundle {
-                    // texture: asset_server.load("./asteroid1.png"),
-                    texture: asset_server.load("Asteroids/A1__00000.png"),
-                    sprite: Sprite {
-                        // color: Color::rgb(0.25, 0.25, 0.75),
-                        color: Color::rgb(1.2, 1.2, 1.2),
-                        custom_size: Some(Vec2::new(250.0, 250.0)),
-                        ..default()
-                    },
-                    // transform: Transform::from_translation(Vec3::new(-200., -400., 2.)),
-                    transform: Transform::from_translation(Vec3::new(left_or_right, random_y, 2.)),
-                    ..default()
-                })
-                .insert(Enemy {
-                    health: 100.,
-                    collision_damage: 10.,
-                })
-                .insert(Warpable)
-                .insert(ExternalImpulse {
-                    impulse: direction * 0.02,
-                    torque_impulse: 0.07,
-                })
-                .insert(Collider::ball(50.0))
-                .insert(RigidBody::Dynamic)
-                .insert(AdditionalMassProperties::Mass(100.0))
-                .insert(GravityScale(0.))
-                .insert(Velocity::linear(Vec2::new(0., 0.)))
-                .insert(CollisionGroups::new(
-                    Group::GROUP_3,
-                    Group::GROUP_1 | Group::GROUP_2,
-                ))
-                .insert(SolverGroups::new(
-                    Group::GROUP_3,
-                    Group::GROUP_1 | Group::GROUP_2,
-                ))
-                .insert(ActiveEvents::COLLISION_EVENTS);
+struct StalkerEnemyPlugin;
+impl Plugin for StalkerEnemyPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(StalkerSpawner::default())
+            .add_systems(OnExit(GameState::GameOver), reset_stalker_spawner)
+            .add_systems(
+                FixedUpdate,
+                (spawn_stalker, steer_stalkers).run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+fn reset_stalker_spawner(mut spawner: ResMut<StalkerSpawner>) {
+    *spawner = StalkerSpawner::default();
+}
+
+fn spawn_stalker(
+    mut commands: Commands,
+    mut spawner: ResMut<StalkerSpawner>,
+    archetypes: Res<EnemyArchetypes>,
+    time: Res<GameRuntime>,
+    win_size: Res<WindowSize>,
+    camera: Query<&Transform, With<Camera>>,
+) {
+    if time.0.elapsed_secs() < spawner.next_spawn_secs {
+        return;
+    }
+    spawner.next_spawn_secs += spawner.interval_secs;
+
+    let Some(archetype) = archetypes.get("stalker") else {
+        return;
+    };
+    let camera_translation = camera
+        .get_single()
+        .map(|transform| transform.translation.xy())
+        .unwrap_or_default();
+    let mut rng = rand::thread_rng();
+    let x = if rng.gen_bool(0.5) {
+        win_size.left_wall * 1.1
+    } else {
+        win_size.right_wall * 1.1
+    };
+    let y = rng.gen_range(win_size.bottom_wall..win_size.top_wall);
+    let transform = Transform::from_translation((camera_translation + Vec2::new(x, y)).extend(2.));
+    spawn_enemy(&mut commands, archetype, transform)
+        .insert(Stalker {
+            max_speed: 250.,
+            max_force: 40.,
+            separation_radius: 80.,
+        })
+        .insert(LockedAxes::ROTATION_LOCKED)
+        .insert(Damping {
+            linear_damping: 0.5,
+            angular_damping: 5.0,
+        });
+}
+
+type SteeredStalker = (
+    Entity,
+    &'static mut ExternalImpulse,
+    &'static mut Transform,
+    &'static Velocity,
+    &'static Stalker,
+);
+
+// Seek towards the player while keeping some distance from other stalkers.
+fn steer_stalkers(
+    mut stalkers: Query<SteeredStalker, (Without<Player>, Without<Stunned>)>,
+    player: Query<&Transform, With<Player>>,
+) {
+    let Ok(player_transform) = player.get_single() else {
+        return;
+    };
+    let positions: Vec<(Entity, Vec2)> = stalkers
+        .iter()
+        .map(|(entity, _, transform, _, _)| (entity, transform.translation.xy()))
+        .collect();
+
+    for (entity, mut ext_impulse, mut transform, velocity, stalker) in stalkers.iter_mut() {
+        let position = transform.translation.xy();
+        let desired =
+            (player_transform.translation.xy() - position).normalize_or_zero() * stalker.max_speed;
+        let seek = desired - velocity.linvel;
+
+        let mut separation = Vec2::ZERO;
+        for (other, other_position) in positions.iter() {
+            let offset = position - *other_position;
+            let distance = offset.length();
+            if *other != entity && distance > 0. && distance < stalker.separation_radius {
+                // Closer neighbours push harder.
+                separation += offset.normalize() * (stalker.separation_radius - distance);
+            }
         }
 
-        wave.0 += 1;
-    } else if elapsed_minutes > 1.5 && wave.0 == 3 {
-        for _ in 0..80 {
-            let random_x = rng.gen_range(-1000. ..1000.) as f32;
-            let random_y = rng.gen_range(-1000. ..1000.) as f32;
-            let direction = player_transform.translation.xy() - Vec2::new(random_x, random_y);
-            let left_or_right = if rng.gen_bool(0.5) {
-                let left_pad = win_size.left_wall;
-                rng.gen_range(left_pad * 1.2..left_pad) as f32
-            } else {
-                let right_pad = win_size.right_wall;
-                rng.gen_range(right_pad..right_pad * 1.2) as f32
-            };
-            commands
-                .spawn(SpriteBundle {
-                    texture: asset_server.load("Asteroids/A4__00001.png"),
-                    sprite: Sprite {
-                        // color: Color::rgb(0.25, 0.25, 0.75),
-                        color: Color::rgb(1.2, 1.2, 1.2),
-                        custom_size: Some(Vec2::new(200.0, 200.0)),
-                        ..default()
-                    },
-                    // transform: Transform::from_translation(Vec3::new(-200., -400., 2.)),
-                    transform: Transform::from_translation(Vec3::new(left_or_right, random_y, 2.)),
-                    ..default()
-                })
-                .insert(Enemy {
-                    health: 100.,
-                    collision_damage: 10.,
-                })
-                .insert(Warpable)
-                .insert(ExternalImpulse {
-                    impulse: direction * 0.02,
-                    torque_impulse: 0.07,
-                })
-                .insert(Collider::ball(40.0))
-                .insert(RigidBody::Dynamic)
-                .insert(AdditionalMassProperties::Mass(100.0))
-                .insert(GravityScale(0.))
-                .insert(Velocity::linear(Vec2::new(0., 0.)))
-                .insert(CollisionGroups::new(
-                    Group::GROUP_3,
-                    Group::GROUP_1 | Group::GROUP_2,
-                ))
-                .insert(SolverGroups::new(
-                    Group::GROUP_3,
-                    Group::GROUP_1 | Group::GROUP_2,
-                ))
-                .insert(ActiveEvents::COLLISION_EVENTS);
+        let steering = (seek + separation * 2.).clamp_length_max(stalker.max_force);
+        ext_impulse.impulse = steering;
+
+        if velocity.linvel.length_squared() > 1. {
+            transform.rotation =
+                Quat::from_rotation_arc(Vec3::Y, velocity.linvel.normalize().extend(0.));
         }
+    }
+}
+
+#[derive(Component)]
+pub struct Turret {
+    pub fire_cooldown: Timer,
+    pub projectile_speed: f32,
+    pub projectile_damage: f32,
+}
+#[derive(Component)]
+pub struct EnemyProjectile {
+    pub damage: f32,
+    pub lifetime: Timer,
+}
+#[derive(Resource)]
+struct TurretSpawner {
+    next_spawn_secs: f32,
+    interval_secs: f32,
+}
 
-        wave.0 += 1;
+impl Default for TurretSpawner {
+    fn default() -> Self {
+        TurretSpawner {
+            next_spawn_secs: 45.,
+            interval_secs: 30.,
+        }
     }
 }
+#[derive(Resource)]
+struct EnemyProjectileTexture(Handle<Image>);
 
-struct StalkerEnemyPlugin;
-impl Plugin for StalkerEnemyPlugin {
+struct TurretEnemyPlugin;
+impl Plugin for TurretEnemyPlugin {
     fn build(&self, app: &mut App) {
-        debug!("");
-        app.add_systems(FixedUpdate, spawn_stalker);
+        app.insert_resource(TurretSpawner::default())
+            .add_systems(Startup, setup_enemy_projectiles)
+            .add_systems(OnExit(GameState::GameOver), reset_turret_spawner)
+            .add_systems(
+                Update,
+                (
+                    spawn_turret,
+                    aim_turrets,
+                    fire_turrets,
+                    despawn_enemy_projectiles,
+                )
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+fn setup_enemy_projectiles(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(EnemyProjectileTexture(asset_server.load("Lasers/01.png")));
+}
+
+fn reset_turret_spawner(mut spawner: ResMut<TurretSpawner>) {
+    *spawner = TurretSpawner::default();
+}
+
+fn spawn_turret(
+    mut commands: Commands,
+    mut spawner: ResMut<TurretSpawner>,
+    archetypes: Res<EnemyArchetypes>,
+    time: Res<GameRuntime>,
+    win_size: Res<WindowSize>,
+    camera: Query<&Transform, With<Camera>>,
+) {
+    if time.0.elapsed_secs() < spawner.next_spawn_secs {
+        return;
+    }
+    spawner.next_spawn_secs += spawner.interval_secs;
+
+    let Some(archetype) = archetypes.get("turret") else {
+        return;
+    };
+    let camera_translation = camera
+        .get_single()
+        .map(|transform| transform.translation.xy())
+        .unwrap_or_default();
+
+    // Pick a point along one of the four edges, just inside the screen.
+    let mut rng = rand::thread_rng();
+    let (left, right) = (win_size.left_wall * 0.9, win_size.right_wall * 0.9);
+    let (bottom, top) = (win_size.bottom_wall * 0.9, win_size.top_wall * 0.9);
+    let edge_point = match rng.gen_range(0..4) {
+        0 => Vec2::new(left, rng.gen_range(bottom..top)),
+        1 => Vec2::new(right, rng.gen_range(bottom..top)),
+        2 => Vec2::new(rng.gen_range(left..right), bottom),
+        _ => Vec2::new(rng.gen_range(left..right), top),
+    };
+    let transform = Transform::from_translation((camera_translation + edge_point).extend(2.));
+    spawn_enemy(&mut commands, archetype, transform)
+        .remove::<Warpable>()
+        .insert(RigidBody::Fixed)
+        .insert(Turret {
+            fire_cooldown: Timer::from_seconds(1.5, TimerMode::Repeating),
+            projectile_speed: 400.,
+            projectile_damage: 15.,
+        });
+}
+
+type AimingTurret = (With<Turret>, Without<Player>, Without<Stunned>);
+
+fn aim_turrets(
+    mut turrets: Query<&mut Transform, AimingTurret>,
+    player: Query<&Transform, With<Player>>,
+) {
+    let Ok(player_transform) = player.get_single() else {
+        return;
+    };
+    for mut transform in turrets.iter_mut() {
+        let to_player = (player_transform.translation - transform.translation)
+            .xy()
+            .normalize_or_zero();
+        if to_player != Vec2::ZERO {
+            transform.rotation = Quat::from_rotation_arc(Vec3::Y, to_player.extend(0.));
+        }
+    }
+}
+
+fn fire_turrets(
+    mut commands: Commands,
+    mut turrets: Query<(&Transform, &mut Turret), Without<Stunned>>,
+    texture: Res<EnemyProjectileTexture>,
+    time: Res<Time>,
+) {
+    for (transform, mut turret) in turrets.iter_mut() {
+        if !turret.fire_cooldown.tick(time.delta()).just_finished() {
+            continue;
+        }
+        let direction = transform.rotation.mul_vec3(Vec3::Y).xy();
+        let angle = direction.y.atan2(direction.x);
+        commands
+            .spawn(SpriteBundle {
+                texture: texture.0.clone(),
+                sprite: Sprite {
+                    custom_size: Some(Vec2::new(48., 24.)),
+                    ..default()
+                },
+                transform: Transform::from_translation(
+                    transform.translation + (direction * 45.).extend(0.),
+                )
+                .with_rotation(Quat::from_rotation_z(angle)),
+                ..default()
+            })
+            .insert(EnemyProjectile {
+                damage: turret.projectile_damage,
+                lifetime: Timer::from_seconds(4., TimerMode::Once),
+            })
+            .insert(Collider::ball(6.))
+            .insert(Sensor)
+            .insert(RigidBody::Dynamic)
+            .insert(GravityScale(0.))
+            .insert(Velocity::linear(direction * turret.projectile_speed))
+            // Lets save_velocity_system freeze enemy fire while paused.
+            .insert(ExternalImpulse::default())
+            // Only the player (GROUP_1) reacts to enemy fire.
+            .insert(CollisionGroups::new(Group::GROUP_4, Group::GROUP_1))
+            .insert(ActiveEvents::COLLISION_EVENTS);
+    }
+}
+
+fn despawn_enemy_projectiles(
+    mut commands: Commands,
+    mut projectiles: Query<(Entity, &mut EnemyProjectile)>,
+    time: Res<Time>,
+) {
+    for (entity, mut projectile) in projectiles.iter_mut() {
+        if projectile.lifetime.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
     }
 }
 
-fn spawn_stalker(mut commands: Commands, keyboard_input: Res<Input<KeyCode>>) {
-    // TODO: replace with a clock.
-    if keyboard_input.just_pressed(KeyCode::S) {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn archetypes_lookup_by_name() {
+        let archetypes = enemy_archetypes(|_| Handle::default());
+        let armored = archetypes
+            .get("asteroid_armored")
+            .expect("asteroid_armored is registered");
+        assert_eq!(armored.armor, 0.6);
+        assert!(archetypes.get("asteroid_shielded").unwrap().shield > 0.);
+        assert_eq!(archetypes.get("asteroid_large").unwrap().tier, 2);
+        assert_eq!(archetypes.get("asteroid_medium").unwrap().tier, 1);
+        assert_eq!(archetypes.get("asteroid_small").unwrap().tier, 0);
+        assert!(archetypes.get("asteroid_huge").is_none());
+    }
+
+    #[test]
+    fn default_waves_use_registered_archetypes() {
+        let archetypes = enemy_archetypes(|_| Handle::default());
+        for wave in WaveTable::default().waves {
+            assert!(
+                archetypes.get(&wave.archetype).is_some(),
+                "unknown archetype {}",
+                wave.archetype
+            );
+        }
+    }
+
+    #[test]
+    fn emp_cracks_the_shield_before_damage_gets_through() {
+        let mut shield = Shield { strength: 30. };
+        let emp = Emp {
+            stun_length: 1.,
+            shield_damage: 20.,
+        };
+        assert!(matches!(
+            resolve_hit(1., 0., Some(&mut shield), None),
+            Penetration::Glance
+        ));
+        assert_eq!(shield.strength, 30.);
+        assert!(matches!(
+            resolve_hit(1., 0., Some(&mut shield), Some(&emp)),
+            Penetration::Glance
+        ));
+        assert_eq!(shield.strength, 10.);
+        assert!(matches!(
+            resolve_hit(1., 0., Some(&mut shield), Some(&emp)),
+            Penetration::PierceThrough
+        ));
+        assert_eq!(shield.strength, 0.);
+        assert!(matches!(
+            resolve_hit(1., 0., Some(&mut shield), None),
+            Penetration::PierceThrough
+        ));
+    }
+
+    #[test]
+    fn ricochet_reflects_off_the_normal() {
+        let bounced = ricochet(Vec2::new(3., -4.), Vec2::Y, 1.);
+        assert_eq!(bounced, Vec2::new(3., 4.));
+        let damped = ricochet(Vec2::new(3., -4.), Vec2::Y, 0.5);
+        assert_eq!(damped, Vec2::new(3., 2.));
+    }
+
+    #[test]
+    fn ricochet_ignores_velocity_leaving_the_surface() {
+        let leaving = Vec2::new(3., 4.);
+        assert_eq!(ricochet(leaving, Vec2::Y, 1.), leaving);
+    }
 }
@@ -24,8 +24,9 @@ impl Plugin for ParallaxBackgroundPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(ParallaxPlugin)
             .add_systems(
-                Startup,
-                initialize_camera_system.run_if(in_state(GameState::Playing)),
+                OnEnter(GameState::Playing),
+                initialize_camera_system
+                    .run_if(not(any_with_component::<ParallaxCameraComponent>())),
             )
             .add_systems(
                 Update,
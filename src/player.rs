@@ -1,7 +1,10 @@
 use crate::constants::*;
+use crate::controls::{ActionState, PlayerAction};
 use crate::game_ui::{GameInterfacePlugin, GameRuntime, GameState};
-use crate::guns::{Projectile, WeaponPlugin, BALL_SIZE};
-use crate::mobs::Enemy;
+use crate::guns::{Multishot, Projectile, WeaponPlugin, BALL_SIZE};
+use crate::mobs::{Enemy, EnemyProjectile};
+use crate::status::StatusEffectPlugin;
+use crate::upgrades::UpgradePlugin;
 
 use bevy::ecs::system::ParamSet;
 use bevy::prelude::*;
@@ -17,10 +20,13 @@ use bevy::{
 use bevy_cursor::prelude::*;
 use bevy_hanabi::prelude::*;
 use bevy_rapier2d::prelude::*;
-use std::time::{Duration, Instant}; // TODO: https://bevy-cheatbook.github.io/pitfalls/time.html
 
 const BASE_MOVESPEED: f32 = 150.0;
 const PROJECTILE_LIMIT: i32 = 40;
+// Exhaust particles per second at full thrust.
+const EXHAUST_RATE: f32 = 400.;
+const EXHAUST_RATE_STEP: f32 = 40.;
+const EXHAUST_OFFSET: f32 = 24.;
 
 #[derive(Event)]
 pub struct LevelUpEvent;
@@ -32,7 +38,13 @@ pub struct WindowSize {
     pub bottom_wall: f32,
 }
 #[derive(Component)]
-pub struct ExhaustEffect;
+pub struct ExhaustEffect {
+    // Particles per second the spawner was last set to.
+    rate: f32,
+}
+/// Impulse the player is thrusting with this step, zero when coasting.
+#[derive(Component, Default)]
+pub struct Thrust(pub Vec2);
 #[derive(Component)]
 pub struct Player {
     pub health_current: f32,
@@ -40,11 +52,24 @@ pub struct Player {
     pub exp_current: f32,
     pub exp_max: f32,
     pub level: u16,
-    move_speed: f32,
+    pub move_speed: f32,
 }
 #[derive(Component)]
 pub struct Warpable;
 
+impl Default for Player {
+    fn default() -> Self {
+        Player {
+            health_current: 500.,
+            health_max: 500.,
+            exp_current: 0.,
+            exp_max: 1000.,
+            level: 1,
+            move_speed: BASE_MOVESPEED,
+        }
+    }
+}
+
 pub struct PlayerPlugin;
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
@@ -53,22 +78,27 @@ impl Plugin for PlayerPlugin {
             .add_plugins(WeaponPlugin)
             .add_plugins(CursorInfoPlugin)
             .add_plugins(GameInterfacePlugin)
-            .add_systems(Startup, (setup_player).run_if(in_state(GameState::Playing)))
-            // .add_systems(
-            //     Update,
-            //     (add_thrust_particles_to_ship, update_thrust_particles),
-            // )
+            .add_plugins(UpgradePlugin)
+            .add_plugins(StatusEffectPlugin)
+            .add_systems(
+                OnEnter(GameState::Playing),
+                setup_player.run_if(not(any_with_component::<Player>())),
+            )
+            .add_systems(Update, add_thrust_particles_to_ship)
+            .add_systems(OnExit(GameState::Playing), stop_thrust_particles)
             .add_systems(
                 Update,
                 (handle_player_collision, ship_warp).run_if(in_state(GameState::Playing)),
             )
+            .add_systems(OnExit(GameState::GameOver), reset_player)
             .add_systems(
                 FixedUpdate,
                 (
                     handle_projectile_mod.run_if(on_event::<LevelUpEvent>()),
                     update_winsize,
                     modify_player_translation,
-                    look_at_cursor,
+                    update_thrust_particles.after(modify_player_translation),
+                    look_at_aim,
                 )
                     .run_if(in_state(GameState::Playing)),
             );
@@ -100,14 +130,8 @@ fn setup_player(
             transform: Transform::from_translation(Vec3::new(0., 0., 2.)),
             ..default()
         })
-        .insert(Player {
-            health_current: 500.,
-            health_max: 500.,
-            exp_current: 0.,
-            exp_max: 1000.,
-            level: 1,
-            move_speed: BASE_MOVESPEED,
-        })
+        .insert(Player::default())
+        .insert(Thrust::default())
         .insert(Warpable)
         .insert(Velocity::zero())
         .insert(ExternalImpulse {
@@ -126,52 +150,92 @@ fn setup_player(
         ))
         .insert(AdditionalMassProperties::Mass(10.0))
         .insert(GravityScale(0.))
-        .insert(CollisionGroups::new(Group::GROUP_1, Group::GROUP_3))
+        .insert(CollisionGroups::new(
+            Group::GROUP_1,
+            Group::GROUP_3 | Group::GROUP_4,
+        ))
         .insert(SolverGroups::new(Group::GROUP_1, Group::GROUP_3));
 }
 
-fn look_at_cursor(cursor: Res<CursorInfo>, mut player_query: Query<&mut Transform, With<Player>>) {
-    let mut player_transform = player_query.single_mut();
+fn reset_player(
+    mut commands: Commands,
+    mut player_query: Query<(
+        Entity,
+        &mut Player,
+        &mut Transform,
+        &mut Velocity,
+        &mut Visibility,
+    )>,
+    camera: Query<&Transform, (With<Camera>, Without<Player>)>,
+) {
+    // The camera keeps drifting, so restart in the middle of wherever it is now.
+    let camera_translation = camera
+        .get_single()
+        .map(|transform| transform.translation.xy())
+        .unwrap_or_default();
+    for (entity, mut player, mut transform, mut velocity, mut visibility) in player_query.iter_mut()
+    {
+        commands.entity(entity).remove::<Multishot>();
+        *player = Player::default();
+        transform.translation = camera_translation.extend(2.);
+        *velocity = Velocity::zero();
+        *visibility = Visibility::Visible;
+    }
+}
+
+// Face the right stick in twin-stick mode, the cursor otherwise.
+fn look_at_aim(
+    cursor: Res<CursorInfo>,
+    actions: Res<ActionState>,
+    mut player_query: Query<&mut Transform, With<Player>>,
+) {
+    let Ok(mut player_transform) = player_query.get_single_mut() else {
+        return;
+    };
     // get the player translation in 2D
     let player_translation = player_transform.translation.xy();
 
-    if let Some(cursor_pos) = cursor.position() {
-        let to_player = (player_translation - cursor_pos).normalize();
-
-        // get the quaternion to rotate from the initial enemy facing direction to the direction
-        // facing the player
-        let rotate_to_player = Quat::from_rotation_arc(-Vec3::Y, to_player.extend(0.));
-
-        // rotate the enemy to face the player
-        player_transform.rotation = rotate_to_player;
+    if let Some(direction) = actions.aim_direction(player_translation, cursor.position()) {
+        // the ship's nose is its +Y
+        player_transform.rotation = Quat::from_rotation_arc(Vec3::Y, direction.extend(0.));
     }
 }
 
 fn modify_player_translation(
-    mut query: Query<(&mut ExternalImpulse, &Transform, &Player), With<Player>>,
-    keyboard_input: Res<Input<KeyCode>>,
+    mut query: Query<(&mut ExternalImpulse, &mut Thrust, &Transform, &Player), With<Player>>,
+    actions: Res<ActionState>,
     cursor: Res<CursorInfo>,
 ) {
-    match cursor.position() {
-        Some(cursor_direction) => {
-            let (mut ext_impulse, transform, player) = query.single_mut();
-            let direction = cursor_direction - transform.translation.truncate();
-
-            if keyboard_input.pressed(KeyCode::Space) {
-                ext_impulse.impulse = direction.normalize() * player.move_speed;
-            }
-            if keyboard_input.pressed(KeyCode::A) {
-                let right_direction = Quat::from_rotation_z(std::f32::consts::FRAC_PI_2)
-                    .mul_vec3(direction.extend(0.));
-                ext_impulse.impulse = right_direction.xy().normalize() * (player.move_speed * 0.2);
-            }
-            if keyboard_input.pressed(KeyCode::F) {
-                let left_direction = Quat::from_rotation_z(-std::f32::consts::FRAC_PI_2)
-                    .mul_vec3(direction.extend(0.));
-                ext_impulse.impulse = left_direction.xy().normalize() * (player.move_speed * 0.2);
-            }
-        }
-        _ => (),
+    let Ok((mut ext_impulse, mut thrust, transform, player)) = query.get_single_mut() else {
+        return;
+    };
+    thrust.0 = Vec2::ZERO;
+    // With nothing to aim at, fly wherever the nose is already pointing.
+    let direction = actions
+        .aim_direction(transform.translation.truncate(), cursor.position())
+        .unwrap_or_else(|| transform.rotation.mul_vec3(Vec3::Y).xy());
+
+    if actions.pressed(PlayerAction::Thrust) {
+        ext_impulse.impulse = direction.normalize() * player.move_speed;
+        thrust.0 = ext_impulse.impulse;
+    }
+    if actions.pressed(PlayerAction::StrafeLeft) {
+        let right_direction =
+            Quat::from_rotation_z(std::f32::consts::FRAC_PI_2).mul_vec3(direction.extend(0.));
+        ext_impulse.impulse = right_direction.xy().normalize() * (player.move_speed * 0.2);
+        thrust.0 = ext_impulse.impulse;
+    }
+    if actions.pressed(PlayerAction::StrafeRight) {
+        let left_direction =
+            Quat::from_rotation_z(-std::f32::consts::FRAC_PI_2).mul_vec3(direction.extend(0.));
+        ext_impulse.impulse = left_direction.xy().normalize() * (player.move_speed * 0.2);
+        thrust.0 = ext_impulse.impulse;
+    }
+    // The left stick thrusts straight along itself, independent of the aim.
+    let stick = actions.move_stick();
+    if stick != Vec2::ZERO {
+        ext_impulse.impulse = stick * player.move_speed;
+        thrust.0 = ext_impulse.impulse;
     }
 }
 
@@ -231,33 +295,45 @@ fn ship_warp(
 
 fn handle_player_collision(
     mut commands: Commands,
-    // mut bullets: Query<(Entity, &mut Velocity, &Projectile), With<EnemyProjectile>>,
+    bullets: Query<(Entity, &EnemyProjectile)>,
     mut player: Query<(Entity, &mut Player)>,
     mut enemies: Query<(Entity, &mut Enemy), With<Enemy>>,
     mut contact_events: EventReader<CollisionEvent>,
+    mut stopwatch: ResMut<GameRuntime>,
+    mut gamestate: ResMut<NextState<GameState>>,
 ) {
     for contact_event in contact_events.read() {
-        if let CollisionEvent::Started(entity1, entity2, x) = contact_event {
+        if let CollisionEvent::Started(entity1, entity2, _) = contact_event {
             let player = player.iter_mut().find(|(player_entity, _)| {
                 *player_entity == *entity1 || *player_entity == *entity2
             });
+            let Some((player_entity, mut player_data)) = player else {
+                continue;
+            };
 
             let enemy = enemies
                 .iter_mut()
                 .find(|(enemy_entity, _)| *enemy_entity == *entity1 || *enemy_entity == *entity2);
+            let bullet = bullets.iter().find(|(bullet_entity, _)| {
+                *bullet_entity == *entity1 || *bullet_entity == *entity2
+            });
 
-            if let (Some((player_entity, mut player_data)), Some((enemy_entity, mut enemy_data))) =
-                (player, enemy)
-            {
+            if let Some((_, enemy_data)) = enemy {
                 // enemy_data.health -= player_data.collision_damage;
                 player_data.health_current -= enemy_data.collision_damage;
-                if player_data.health_current < 0. {
-                    info!("Deleting entity. {:?}", enemy_entity);
-                    // TODO: end the game.
-                    commands.entity(player_entity).remove::<Visibility>();
-                    commands.entity(player_entity).insert(Visibility::Hidden);
-                    // commands.entity(player_entity).despawn_recursive();
-                }
+            } else if let Some((bullet_entity, bullet_data)) = bullet {
+                player_data.health_current -= bullet_data.damage;
+                commands.entity(bullet_entity).despawn_recursive();
+            } else {
+                continue;
+            }
+
+            if player_data.health_current < 0. {
+                info!("Player destroyed. {:?}", player_entity);
+                commands.entity(player_entity).remove::<Visibility>();
+                commands.entity(player_entity).insert(Visibility::Hidden);
+                stopwatch.0.pause();
+                gamestate.set(GameState::GameOver);
             }
         }
     }
@@ -270,150 +346,104 @@ fn handle_projectile_mod(mut projectile_query: Query<&mut Projectile>) {
     }
 }
 
-fn setup_particles(mut commands: Commands, mut effects: ResMut<Assets<EffectAsset>>) {
-    let mut color_gradient1 = Gradient::new();
-    color_gradient1.add_key(0.0, Vec4::new(4.0, 4.0, 4.0, 1.0));
-    color_gradient1.add_key(0.1, Vec4::new(4.0, 4.0, 0.0, 1.0));
-    color_gradient1.add_key(0.9, Vec4::new(4.0, 0.0, 0.0, 1.0));
-    color_gradient1.add_key(1.0, Vec4::new(4.0, 0.0, 0.0, 0.0));
-
-    let mut size_gradient1 = Gradient::new();
-    size_gradient1.add_key(0.0, Vec2::splat(0.1));
-    size_gradient1.add_key(0.3, Vec2::splat(0.1));
-    size_gradient1.add_key(1.0, Vec2::splat(0.0));
-
-    let writer = ExprWriter::new();
-
-    // Give a bit of variation by randomizing the age per particle. This will
-    // control the starting color and starting size of particles.
-    let age = writer.lit(0.).uniform(writer.lit(0.2)).expr();
-    let init_age = SetAttributeModifier::new(Attribute::AGE, age);
-
-    // Give a bit of variation by randomizing the lifetime per particle
-    let lifetime = writer.lit(0.8).uniform(writer.lit(1.2)).expr();
-    let init_lifetime = SetAttributeModifier::new(Attribute::LIFETIME, lifetime);
-
-    // Add constant downward acceleration to simulate gravity
-    let accel = writer.lit(Vec3::Y * -8.).expr();
-    let update_accel = AccelModifier::new(accel);
-
-    // Add drag to make particles slow down a bit after the initial explosion
-    let drag = writer.lit(5.).expr();
-    let update_drag = LinearDragModifier::new(drag);
-
-    let init_pos = SetPositionSphereModifier {
-        center: writer.lit(Vec3::ZERO).expr(),
-        radius: writer.lit(2.).expr(),
-        dimension: ShapeDimension::Volume,
-    };
-
-    // Give a bit of variation by randomizing the initial speed
-    let init_vel = SetVelocitySphereModifier {
-        center: writer.lit(Vec3::ZERO).expr(),
-        speed: (writer.rand(ScalarType::Float) * writer.lit(20.) + writer.lit(60.)).expr(),
-    };
-
-    let effect = EffectAsset::new(
-        32768,
-        Spawner::burst(2500.0.into(), 2.0.into()),
-        writer.finish(),
-    )
-    .with_name("firework")
-    .init(init_pos)
-    .init(init_vel)
-    .init(init_age)
-    .init(init_lifetime)
-    .update(update_drag)
-    .update(update_accel)
-    .render(ColorOverLifetimeModifier {
-        gradient: color_gradient1,
-    })
-    .render(SizeOverLifetimeModifier {
-        gradient: size_gradient1,
-        screen_space_size: false,
-    });
-
-    let effect1 = effects.add(effect);
-
-    commands.spawn((
-        Name::new("firework"),
-        ParticleEffectBundle {
-            effect: ParticleEffect::new(effect1),
-            transform: Transform::IDENTITY,
-            ..Default::default()
-        },
-    ));
-}
-
-// Add a Particle Effect to every new Ship created
+// Add a single exhaust effect to every new Ship, update_thrust_particles drives its rate.
 fn add_thrust_particles_to_ship(
     mut commands: Commands,
     mut effects: ResMut<Assets<EffectAsset>>,
-    // added_ships: Query<Entity, Added<Player>>,
-    keyboard_input: Res<Input<KeyCode>>,
-    added_ships: Query<Entity, With<Player>>,
+    added_ships: Query<Entity, Added<Player>>,
 ) {
-    if keyboard_input.pressed(KeyCode::Space) {
-        for ship_entity in added_ships.iter() {
-            // For Ship exhaust, we store a particle effects on the player
-
-            let writer = ExprWriter::new();
-            let lifetime = writer.lit(0.1).expr();
-            // Gradient for particle color evolution
-            let mut gradient = Gradient::new();
-            gradient.add_key(0.0, Vec4::new(0.5, 0.4, 0.7, 0.8));
-            gradient.add_key(0.5, Vec4::new(1.0, 0.8, 0.0, 0.8));
-            gradient.add_key(1.0, Vec4::ZERO);
-            let init_pos = SetPositionCone3dModifier {
-                height: writer.lit(-5.0).expr(),
-                base_radius: writer.lit(2.).expr(),
-                top_radius: writer.lit(1.).expr(),
-                dimension: ShapeDimension::Volume,
-            };
-            let init_vel = SetVelocitySphereModifier {
-                speed: writer.lit(100.0).uniform(writer.lit(400.0)).expr(),
-                center: writer.lit(Vec3::new(0.0, 1.0, 0.0)).expr(),
-            };
-            let effect = effects.add(
-                EffectAsset::new(16024, Spawner::once(10.0.into(), false), writer.finish())
-                    .with_name("Exhaust")
-                    .init(init_pos)
-                    .init(init_vel)
-                    .init(SetAttributeModifier::new(Attribute::LIFETIME, lifetime))
-                    .render(ColorOverLifetimeModifier { gradient })
-                    .render(SizeOverLifetimeModifier {
-                        gradient: Gradient::constant(Vec2::splat(2.)),
-                        screen_space_size: true,
-                    }),
-            );
-            commands.entity(ship_entity).with_children(|parent| {
-                parent.spawn((
-                    ParticleEffectBundle {
-                        effect: ParticleEffect::new(effect).with_z_layer_2d(Some(10.)),
-                        transform: Transform::from_translation(Vec3::new(0.0, -4.0, 0.0)),
-                        ..default()
-                    },
-                    ExhaustEffect,
-                ));
-            });
-        }
+    for ship_entity in added_ships.iter() {
+        let writer = ExprWriter::new();
+        let lifetime = writer.lit(0.1).expr();
+        // Gradient for particle color evolution
+        let mut gradient = Gradient::new();
+        gradient.add_key(0.0, Vec4::new(0.5, 0.4, 0.7, 0.8));
+        gradient.add_key(0.5, Vec4::new(1.0, 0.8, 0.0, 0.8));
+        gradient.add_key(1.0, Vec4::ZERO);
+        let init_pos = SetPositionCone3dModifier {
+            height: writer.lit(-5.0).expr(),
+            base_radius: writer.lit(2.).expr(),
+            top_radius: writer.lit(1.).expr(),
+            dimension: ShapeDimension::Volume,
+        };
+        let init_vel = SetVelocitySphereModifier {
+            speed: writer.lit(100.0).uniform(writer.lit(400.0)).expr(),
+            center: writer.lit(Vec3::new(0.0, 1.0, 0.0)).expr(),
+        };
+        let spawner = Spawner::rate(EXHAUST_RATE.into()).with_starts_active(false);
+        let effect = effects.add(
+            EffectAsset::new(16024, spawner, writer.finish())
+                .with_name("Exhaust")
+                .init(init_pos)
+                .init(init_vel)
+                .init(SetAttributeModifier::new(Attribute::LIFETIME, lifetime))
+                .render(ColorOverLifetimeModifier { gradient })
+                .render(SizeOverLifetimeModifier {
+                    gradient: Gradient::constant(Vec2::splat(2.)),
+                    screen_space_size: true,
+                }),
+        );
+        commands.entity(ship_entity).with_children(|parent| {
+            parent.spawn((
+                ParticleEffectBundle {
+                    effect: ParticleEffect::new(effect).with_z_layer_2d(Some(10.)),
+                    transform: Transform::from_translation(Vec3::new(0.0, -EXHAUST_OFFSET, 0.0)),
+                    ..default()
+                },
+                ExhaustEffect { rate: 0. },
+            ));
+        });
     }
 }
 
-// Trigger a new particle spawning whenever the Ship Impulse is non-0
+// Scale the exhaust with the thrust applied this step, pointing it away from the thrust.
 fn update_thrust_particles(
-    // player: Query<(&ActionState<PlayerAction>, &Children), Changed<ActionState<PlayerAction>>>,
-    player: Query<(Entity, &Children), With<Player>>,
-    keyboard_input: Res<Input<KeyCode>>,
-    mut exhaust_effect: Query<&mut EffectSpawner, With<ExhaustEffect>>,
+    player: Query<(&Thrust, &Transform, &Player, &Children)>,
+    mut exhaust_effect: Query<
+        (
+            &ParticleEffect,
+            &mut EffectSpawner,
+            &mut Transform,
+            &mut ExhaustEffect,
+        ),
+        Without<Player>,
+    >,
+    effects: Res<Assets<EffectAsset>>,
 ) {
-    for (action_state, children) in player.iter() {
-        if keyboard_input.pressed(KeyCode::Space) {
-            for &child in children.iter() {
-                if let Ok(mut spawner) = exhaust_effect.get_mut(child) {
-                    spawner.reset();
-                }
+    for (thrust, player_transform, player, children) in player.iter() {
+        let strength = (thrust.0.length() / player.move_speed).min(1.);
+        // Bucket the rate, the spawner state is rebuilt whenever it changes.
+        let rate = (strength * EXHAUST_RATE / EXHAUST_RATE_STEP).round() * EXHAUST_RATE_STEP;
+        for &child in children.iter() {
+            let Ok((effect, mut spawner, mut transform, mut exhaust)) =
+                exhaust_effect.get_mut(child)
+            else {
+                continue;
+            };
+            if rate > 0. {
+                let local = (player_transform.rotation.inverse() * thrust.0.extend(0.)).normalize();
+                transform.translation = -local * EXHAUST_OFFSET;
+                transform.rotation = Quat::from_rotation_arc(Vec3::Y, local);
+            }
+            if rate == exhaust.rate {
+                continue;
+            }
+            exhaust.rate = rate;
+            if rate == 0. {
+                spawner.set_active(false);
+            } else if let Some(asset) = effects.get(&effect.handle) {
+                let instance = ParticleEffect::new(effect.handle.clone())
+                    .with_spawner(Spawner::rate(rate.into()));
+                *spawner = EffectSpawner::new(asset, &instance);
+                spawner.set_active(true);
             }
         }
     }
 }
+
+fn stop_thrust_particles(mut exhaust_effect: Query<(&mut EffectSpawner, &mut ExhaustEffect)>) {
+    for (mut spawner, mut exhaust) in exhaust_effect.iter_mut() {
+        spawner.set_active(false);
+        exhaust.rate = 0.;
+    }
+}
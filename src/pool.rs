@@ -0,0 +1,122 @@
+use bevy::prelude::*;
+use std::marker::PhantomData;
+
+/// Free list of pre-spawned entities carrying `T`, so hot paths can reuse them
+/// instead of spawning and despawning every frame.
+#[derive(Resource, Debug)]
+pub struct Pool<T: Component> {
+    free: Vec<Entity>,
+    in_use: usize,
+    // How many entities to add whenever the free list runs low, 0 keeps the pool fixed.
+    pub grow_by: usize,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Component> Pool<T> {
+    pub fn new(grow_by: usize) -> Self {
+        Pool {
+            free: Vec::new(),
+            in_use: 0,
+            grow_by,
+            marker: PhantomData,
+        }
+    }
+
+    /// Hands a freshly spawned entity to the pool, ready to be acquired.
+    pub fn add(&mut self, entity: Entity) {
+        self.free.push(entity);
+    }
+
+    pub fn acquire(&mut self) -> Option<Entity> {
+        let entity = self.free.pop()?;
+        self.in_use += 1;
+        Some(entity)
+    }
+
+    /// Like `acquire`, but falls back to `spawn` rather than coming up empty.
+    pub fn acquire_or_spawn(&mut self, spawn: impl FnOnce() -> Entity) -> Entity {
+        let entity = self.free.pop().unwrap_or_else(spawn);
+        self.in_use += 1;
+        entity
+    }
+
+    /// Returns `entity` to the free list, false if it was already there.
+    pub fn release(&mut self, entity: Entity) -> bool {
+        if self.free.contains(&entity) {
+            return false;
+        }
+        self.free.push(entity);
+        self.in_use = self.in_use.saturating_sub(1);
+        true
+    }
+
+    pub fn needs_growth(&self) -> bool {
+        self.grow_by > 0 && self.free.len() < self.grow_by
+    }
+
+    pub fn in_use(&self) -> usize {
+        self.in_use
+    }
+
+    pub fn len(&self) -> usize {
+        self.in_use + self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Component)]
+    struct Pooled;
+
+    #[test]
+    fn acquire_and_release_track_usage() {
+        let mut pool = Pool::<Pooled>::new(0);
+        let entity = Entity::from_raw(1);
+        pool.add(entity);
+        assert_eq!(pool.acquire(), Some(entity));
+        assert_eq!(pool.in_use(), 1);
+        assert_eq!(pool.acquire(), None);
+        assert!(pool.release(entity));
+        assert_eq!(pool.in_use(), 0);
+        assert_eq!(pool.len(), 1);
+    }
+
+    #[test]
+    fn double_release_is_ignored() {
+        let mut pool = Pool::<Pooled>::new(0);
+        let entity = Entity::from_raw(1);
+        pool.add(entity);
+        pool.acquire();
+        assert!(pool.release(entity));
+        assert!(!pool.release(entity));
+        assert_eq!(pool.len(), 1);
+        assert_eq!(pool.acquire(), Some(entity));
+        assert_eq!(pool.acquire(), None);
+    }
+
+    #[test]
+    fn acquire_or_spawn_falls_back_when_empty() {
+        let mut pool = Pool::<Pooled>::new(0);
+        let spawned = pool.acquire_or_spawn(|| Entity::from_raw(7));
+        assert_eq!(spawned, Entity::from_raw(7));
+        assert_eq!(pool.in_use(), 1);
+    }
+
+    #[test]
+    fn needs_growth_below_grow_by() {
+        let mut pool = Pool::<Pooled>::new(2);
+        assert!(pool.needs_growth());
+        pool.add(Entity::from_raw(1));
+        pool.add(Entity::from_raw(2));
+        assert!(!pool.needs_growth());
+        pool.acquire();
+        assert!(pool.needs_growth());
+        assert!(!Pool::<Pooled>::new(0).needs_growth());
+    }
+}
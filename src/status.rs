@@ -0,0 +1,305 @@
+use crate::game_ui::GameState;
+use crate::guns::{Burn, Projectile, ProjectilePool};
+use crate::mobs::Enemy;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+const BURN_DURATION: f32 = 3.;
+const BURN_TINT: Color = Color::rgb(2.0, 0.7, 0.3);
+// Distance a burning bullet travels between dropping embers.
+const EMBER_SPACING: f32 = 15.;
+const EMBER_LIFETIME: f32 = 1.5;
+// Flashes per second while stunned.
+const STUN_FLICKER_RATE: f32 = 12.;
+// Blade bleed is per second, scaled up so it keeps pace with bullet damage.
+const BLEED_SCALE: f32 = 10.;
+const EMBED_DURATION: f32 = 2.;
+
+/// Damage over time on an enemy, every burning hit adds its dot on top.
+#[derive(Component)]
+pub struct Burning {
+    pub dps: f32,
+    pub duration: Timer,
+    pub original_color: Color,
+}
+
+impl Burning {
+    fn new(dps: f32) -> Self {
+        Burning {
+            dps,
+            duration: Timer::from_seconds(BURN_DURATION, TimerMode::Once),
+            // Filled in by tint_burning once the component lands.
+            original_color: Color::WHITE,
+        }
+    }
+}
+
+/// Embers left behind burning bullets, sets anything crossing them alight.
+#[derive(Component)]
+pub struct BurnHazard {
+    pub dot: f32,
+    lifetime: Timer,
+}
+
+/// Stops an enemy steering, aiming or firing until the timer runs out.
+#[derive(Component)]
+pub struct Stunned {
+    pub duration: Timer,
+}
+
+/// A bullet stuck in the enemy it's parented to, bleeding it until it works loose.
+#[derive(Component)]
+pub struct Embedded {
+    pub bleed: f32,
+    duration: Timer,
+}
+
+impl Embedded {
+    pub fn new(bleed: f32) -> Self {
+        Embedded {
+            bleed,
+            duration: Timer::from_seconds(EMBED_DURATION, TimerMode::Once),
+        }
+    }
+}
+
+#[derive(Component, Default)]
+struct EmberTrail {
+    last_drop: Option<Vec2>,
+    laid: f32,
+}
+
+pub struct StatusEffectPlugin;
+impl Plugin for StatusEffectPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                tint_burning,
+                tick_burning,
+                lay_burn_trails,
+                expire_burn_hazards,
+                ignite_from_hazards.run_if(on_event::<CollisionEvent>()),
+                tick_stunned,
+                tick_embedded,
+            )
+                .run_if(in_state(GameState::Playing)),
+        )
+        .add_systems(OnExit(GameState::GameOver), despawn_burn_hazards);
+    }
+}
+
+/// Sets an enemy on fire, or stacks another `dot` onto an existing burn.
+pub fn ignite(commands: &mut Commands, enemy: Entity, burning: Option<Mut<Burning>>, dot: f32) {
+    match burning {
+        Some(mut burning) => {
+            burning.dps += dot;
+            burning.duration.reset();
+        }
+        None => {
+            commands.entity(enemy).insert(Burning::new(dot));
+        }
+    }
+}
+
+/// Stuns an enemy, a fresh hit only ever extends what's left of an existing stun.
+pub fn stun(commands: &mut Commands, enemy: Entity, stunned: Option<Mut<Stunned>>, seconds: f32) {
+    match stunned {
+        Some(mut stunned) => {
+            if stunned.duration.remaining_secs() < seconds {
+                stunned.duration = Timer::from_seconds(seconds, TimerMode::Once);
+            }
+        }
+        None => {
+            commands.entity(enemy).insert(Stunned {
+                duration: Timer::from_seconds(seconds, TimerMode::Once),
+            });
+        }
+    }
+}
+
+fn tint_burning(mut query: Query<(&mut Burning, &mut Sprite), Added<Burning>>) {
+    for (mut burning, mut sprite) in query.iter_mut() {
+        burning.original_color = sprite.color.with_a(1.);
+        sprite.color = BURN_TINT;
+    }
+}
+
+fn tick_burning(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut Enemy, &mut Burning, &mut Sprite)>,
+    time: Res<Time>,
+) {
+    for (entity, mut enemy, mut burning, mut sprite) in query.iter_mut() {
+        enemy.health -= burning.dps * time.delta_seconds();
+        if burning.duration.tick(time.delta()).finished() {
+            sprite.color = burning.original_color;
+            commands.entity(entity).remove::<Burning>();
+        }
+    }
+}
+
+fn tick_stunned(
+    mut commands: Commands,
+    mut query: Query<(
+        Entity,
+        &mut Stunned,
+        &mut Sprite,
+        Option<&mut ExternalImpulse>,
+    )>,
+    time: Res<Time>,
+) {
+    for (entity, mut stunned, mut sprite, ext_impulse) in query.iter_mut() {
+        // Drop anything queued up before the stun landed.
+        if let Some(mut ext_impulse) = ext_impulse {
+            *ext_impulse = ExternalImpulse::default();
+        }
+        if stunned.duration.tick(time.delta()).finished() {
+            sprite.color.set_a(1.);
+            commands.entity(entity).remove::<Stunned>();
+            continue;
+        }
+        let flicker = (stunned.duration.elapsed_secs() * STUN_FLICKER_RATE).fract();
+        sprite.color.set_a(if flicker < 0.5 { 0.3 } else { 1. });
+    }
+}
+
+fn tick_embedded(
+    mut commands: Commands,
+    mut bullets: Query<(Entity, &Parent, &mut Embedded)>,
+    mut enemies: Query<&mut Enemy>,
+    mut pool: ResMut<ProjectilePool>,
+    time: Res<Time>,
+) {
+    for (entity, parent, mut embedded) in bullets.iter_mut() {
+        if let Ok(mut enemy) = enemies.get_mut(parent.get()) {
+            enemy.health -= embedded.bleed * BLEED_SCALE * time.delta_seconds();
+        }
+        if embedded.duration.tick(time.delta()).finished() {
+            release_embedded(&mut commands, &mut pool, entity);
+        }
+    }
+}
+
+/// Pulls a stuck bullet out of its enemy and hides it back in the projectile pool.
+pub fn release_embedded(commands: &mut Commands, pool: &mut ProjectilePool, bullet: Entity) {
+    pool.release(bullet);
+    commands
+        .entity(bullet)
+        .remove_parent()
+        .remove::<(Embedded, RigidBodyDisabled, ColliderDisabled)>()
+        .insert(Visibility::Hidden)
+        .insert(Velocity::zero())
+        .insert(Transform::from_translation(Vec3::new(10000., 100000., -1.)));
+}
+
+type BurningBullet = (
+    Entity,
+    &'static Transform,
+    &'static Visibility,
+    &'static Burn,
+    Option<&'static mut EmberTrail>,
+);
+
+// Each shot lays at most `trail_length` worth of embers along its path.
+fn lay_burn_trails(
+    mut commands: Commands,
+    mut bullets: Query<BurningBullet, (With<Projectile>, Without<Embedded>)>,
+) {
+    for (entity, transform, visibility, burn, trail) in bullets.iter_mut() {
+        let Some(mut trail) = trail else {
+            commands.entity(entity).insert(EmberTrail::default());
+            continue;
+        };
+        // Back in the pool, start over on the next shot.
+        if *visibility == Visibility::Hidden {
+            *trail = EmberTrail::default();
+            continue;
+        }
+        let position = transform.translation.xy();
+        let Some(last_drop) = trail.last_drop else {
+            trail.last_drop = Some(position);
+            continue;
+        };
+        let travelled = last_drop.distance(position);
+        if travelled < EMBER_SPACING || trail.laid >= burn.trail_length {
+            continue;
+        }
+        trail.laid += travelled;
+        trail.last_drop = Some(position);
+
+        commands
+            .spawn(SpriteBundle {
+                sprite: Sprite {
+                    color: BURN_TINT,
+                    custom_size: Some(Vec2::splat(10.)),
+                    ..default()
+                },
+                transform: Transform::from_translation(position.extend(1.5)),
+                ..default()
+            })
+            .insert(BurnHazard {
+                dot: burn.dot,
+                lifetime: Timer::from_seconds(EMBER_LIFETIME, TimerMode::Once),
+            })
+            .insert(Collider::ball(8.))
+            .insert(Sensor)
+            .insert(CollisionGroups::new(Group::GROUP_2, Group::GROUP_3))
+            .insert(ActiveEvents::COLLISION_EVENTS);
+    }
+}
+
+fn expire_burn_hazards(
+    mut commands: Commands,
+    mut hazards: Query<(Entity, &mut BurnHazard, &mut Sprite)>,
+    time: Res<Time>,
+) {
+    for (entity, mut hazard, mut sprite) in hazards.iter_mut() {
+        hazard.lifetime.tick(time.delta());
+        sprite.color.set_a(hazard.lifetime.percent_left());
+        if hazard.lifetime.finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn ignite_from_hazards(
+    mut commands: Commands,
+    hazards: Query<&BurnHazard>,
+    mut enemies: Query<Option<&mut Burning>, With<Enemy>>,
+    mut contact_events: EventReader<CollisionEvent>,
+) {
+    for contact_event in contact_events.read() {
+        if let CollisionEvent::Started(entity1, entity2, _) = contact_event {
+            let (hazard_entity, enemy_entity) = if hazards.contains(*entity1) {
+                (*entity1, *entity2)
+            } else {
+                (*entity2, *entity1)
+            };
+            let (Ok(hazard), Ok(burning)) =
+                (hazards.get(hazard_entity), enemies.get_mut(enemy_entity))
+            else {
+                continue;
+            };
+            // Crossing a trail keeps an enemy burning rather than stacking once per ember.
+            match burning {
+                Some(mut burning) => {
+                    burning.dps = burning.dps.max(hazard.dot);
+                    burning.duration.reset();
+                }
+                None => {
+                    commands
+                        .entity(enemy_entity)
+                        .insert(Burning::new(hazard.dot));
+                }
+            }
+        }
+    }
+}
+
+fn despawn_burn_hazards(mut commands: Commands, hazards: Query<Entity, With<BurnHazard>>) {
+    for entity in hazards.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
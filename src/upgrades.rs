@@ -0,0 +1,252 @@
+use crate::guns::{Blade, BladeEvent, Burn, Emp, MinionEvent, Multishot, Projectile, Tracking};
+use crate::player::Player;
+
+use bevy::prelude::*;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+// Relative odds of each upgrade showing up on the level up screen.
+const UPGRADE_POOL: [(UpgradeKind, u32); 9] = [
+    (UpgradeKind::Multishot, 10),
+    (UpgradeKind::Burn, 8),
+    (UpgradeKind::Tracking, 8),
+    (UpgradeKind::Emp, 6),
+    (UpgradeKind::Blade, 6),
+    (UpgradeKind::Damage, 15),
+    (UpgradeKind::MaxHealth, 12),
+    (UpgradeKind::MoveSpeed, 12),
+    (UpgradeKind::Drones, 6),
+];
+// Pierce the Blade upgrade adds to every shot per rarity step.
+const BLADE_PIERCE_PER_STEP: f32 = 0.5;
+// Drones added per rarity step.
+const DRONES_PER_STEP: u32 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rarity {
+    Common,
+    Epic,
+    Legendary,
+}
+
+impl Rarity {
+    fn roll(rng: &mut impl Rng) -> Self {
+        match rng.gen_range(0..100) {
+            0..=69 => Rarity::Common,
+            70..=94 => Rarity::Epic,
+            _ => Rarity::Legendary,
+        }
+    }
+
+    /// How many times stronger than a common roll this is.
+    pub fn steps(&self) -> f32 {
+        match self {
+            Rarity::Common => 1.,
+            Rarity::Epic => 2.,
+            Rarity::Legendary => 3.,
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            Rarity::Common => Color::WHITE,
+            Rarity::Epic => Color::PURPLE,
+            Rarity::Legendary => Color::GOLD,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpgradeKind {
+    Multishot,
+    Burn,
+    Tracking,
+    Emp,
+    Blade,
+    Damage,
+    MaxHealth,
+    MoveSpeed,
+    Drones,
+}
+
+/// A level up choice, attached to its button on the level up screen.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Upgrade {
+    pub kind: UpgradeKind,
+    pub rarity: Rarity,
+}
+
+impl Upgrade {
+    pub fn description(&self) -> String {
+        let steps = self.rarity.steps();
+        let effect = match self.kind {
+            UpgradeKind::Multishot => format!("Multishot\n+{} projectiles per shot", steps),
+            UpgradeKind::Burn => format!("Burn\n+{} damage per second", 5. * steps),
+            UpgradeKind::Tracking => format!("Tracking\n+{} homing, -20% damage", 2. * steps),
+            UpgradeKind::Emp => format!(
+                "EMP\n+{}s stun, +{} shield damage",
+                0.5 * steps,
+                10. * steps
+            ),
+            UpgradeKind::Blade => format!(
+                "Blade\n+{} slash damage, +{} pierce",
+                1.2 * steps,
+                BLADE_PIERCE_PER_STEP * steps
+            ),
+            UpgradeKind::Damage => format!("Damage\n+{}%", 10. * steps),
+            UpgradeKind::MaxHealth => format!("Max Health\n+{}", 50. * steps),
+            UpgradeKind::MoveSpeed => format!("Move Speed\n+{}%", 10. * steps),
+            UpgradeKind::Drones => format!("Drones\n+{} minions", DRONES_PER_STEP * steps as u32),
+        };
+        format!("{:?}\n{}", self.rarity, effect)
+    }
+}
+
+/// Draws `count` upgrades of different kinds, weighted by UPGRADE_POOL.
+pub fn draw_upgrades(rng: &mut impl Rng, count: usize) -> Vec<Upgrade> {
+    let mut remaining = UPGRADE_POOL.to_vec();
+    let mut upgrades = Vec::with_capacity(count);
+    while upgrades.len() < count {
+        let Ok(&(kind, _)) = remaining.choose_weighted(rng, |(_, weight)| *weight) else {
+            break;
+        };
+        remaining.retain(|(other, _)| *other != kind);
+        upgrades.push(Upgrade {
+            kind,
+            rarity: Rarity::roll(rng),
+        });
+    }
+    upgrades
+}
+
+#[derive(Event)]
+pub struct UpgradeChosen(pub Upgrade);
+
+pub struct UpgradePlugin;
+impl Plugin for UpgradePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<UpgradeChosen>()
+            .add_systems(Update, apply_upgrade.run_if(on_event::<UpgradeChosen>()));
+    }
+}
+
+// Every projectile stat an upgrade can touch.
+type UpgradableProjectile = (
+    Entity,
+    &'static mut Projectile,
+    &'static mut Blade,
+    Option<&'static mut Burn>,
+    Option<&'static mut Tracking>,
+    Option<&'static mut Emp>,
+);
+
+fn apply_upgrade(
+    mut commands: Commands,
+    mut events: EventReader<UpgradeChosen>,
+    mut player_query: Query<(Entity, &mut Player, Option<&mut Multishot>)>,
+    mut projectile_query: Query<UpgradableProjectile>,
+    mut blade_events: EventWriter<BladeEvent>,
+    mut minion_events: EventWriter<MinionEvent>,
+) {
+    for UpgradeChosen(upgrade) in events.read() {
+        info!("Applying upgrade {:?}", upgrade);
+        let steps = upgrade.rarity.steps();
+        let Ok((player_entity, mut player, multishot)) = player_query.get_single_mut() else {
+            continue;
+        };
+        match upgrade.kind {
+            UpgradeKind::Multishot => match multishot {
+                Some(mut multishot) => multishot.count += steps as i32,
+                None => {
+                    commands.entity(player_entity).insert(Multishot {
+                        count: 1 + steps as i32,
+                        spread: 0.15,
+                        processed: false,
+                    });
+                }
+            },
+            UpgradeKind::MaxHealth => {
+                player.health_max += 50. * steps;
+                player.health_current += 50. * steps;
+            }
+            UpgradeKind::MoveSpeed => player.move_speed *= 1. + 0.1 * steps,
+            UpgradeKind::Drones => minion_events.send(MinionEvent(DRONES_PER_STEP * steps as u32)),
+            UpgradeKind::Blade => {
+                // apply_blade_event grows every projectile by its Blade stats.
+                for (_, _, mut blade, ..) in projectile_query.iter_mut() {
+                    let base = Blade::default();
+                    blade.slash_dmg += base.slash_dmg * steps;
+                    blade.swing_speed += base.swing_speed * steps;
+                    blade.pierce += BLADE_PIERCE_PER_STEP * steps;
+                }
+                blade_events.send(BladeEvent);
+            }
+            UpgradeKind::Damage | UpgradeKind::Burn | UpgradeKind::Tracking | UpgradeKind::Emp => {
+                for (entity, mut projectile, _, burn, tracking, emp) in projectile_query.iter_mut()
+                {
+                    match upgrade.kind {
+                        UpgradeKind::Damage => projectile.damage *= 1. + 0.1 * steps,
+                        UpgradeKind::Burn => match burn {
+                            Some(mut burn) => burn.dot += 5. * steps,
+                            None => {
+                                commands.entity(entity).insert(Burn {
+                                    dot: 5. * steps,
+                                    trail_length: 60.,
+                                });
+                            }
+                        },
+                        UpgradeKind::Tracking => match tracking {
+                            Some(mut tracking) => tracking.pull_strength += 2. * steps,
+                            None => {
+                                commands.entity(entity).insert(Tracking {
+                                    pull_strength: 2. * steps,
+                                    damage_modifier: 0.2,
+                                });
+                            }
+                        },
+                        UpgradeKind::Emp => match emp {
+                            Some(mut emp) => {
+                                emp.stun_length += 0.5 * steps;
+                                emp.shield_damage += 10. * steps;
+                            }
+                            None => {
+                                commands.entity(entity).insert(Emp {
+                                    stun_length: 0.5 * steps,
+                                    shield_damage: 10. * steps,
+                                });
+                            }
+                        },
+                        _ => (),
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn draws_distinct_kinds() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..100 {
+            let upgrades = draw_upgrades(&mut rng, 3);
+            assert_eq!(upgrades.len(), 3);
+            for (index, upgrade) in upgrades.iter().enumerate() {
+                assert!(upgrades[index + 1..]
+                    .iter()
+                    .all(|other| other.kind != upgrade.kind));
+            }
+        }
+    }
+
+    #[test]
+    fn draw_stops_when_the_pool_runs_out() {
+        let mut rng = StdRng::seed_from_u64(7);
+        assert_eq!(draw_upgrades(&mut rng, 20).len(), UPGRADE_POOL.len());
+    }
+}
@@ -0,0 +1,206 @@
+use bevy::utils::thiserror;
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    reflect::TypePath,
+    utils::BoxedFuture,
+};
+use serde::Deserialize;
+use thiserror::Error;
+
+pub const WAVES_PATH: &str = "default.waves.ron";
+
+/// A batch of enemies released once the GameRuntime passes `trigger_secs`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct WaveDefinition {
+    pub trigger_secs: f32,
+    pub archetype: String,
+    pub count: u32,
+    pub spawn_ring: SpawnRing,
+    pub impulse: WaveImpulse,
+}
+
+/// Where enemies appear, relative to the left/right walls of the window.
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct SpawnRing {
+    // 1.0 is exactly on the wall, 1.2 is 20% past it.
+    pub inner: f32,
+    pub outer: f32,
+    // Enemies are scattered between -height..height on the y axis.
+    pub height: f32,
+}
+
+impl SpawnRing {
+    // spawn_wave samples inner..=outer and -height..=height, which panic on empty ranges.
+    fn validate(&self) -> Result<(), String> {
+        if !(self.inner.is_finite() && self.outer.is_finite() && self.height.is_finite()) {
+            return Err("spawn_ring values must be finite".into());
+        }
+        if self.inner > self.outer {
+            return Err(format!(
+                "spawn_ring inner ({}) is past outer ({})",
+                self.inner, self.outer
+            ));
+        }
+        if self.height < 0. {
+            return Err(format!("spawn_ring height ({}) is negative", self.height));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct WaveImpulse {
+    pub linear: f32,
+    pub torque: f32,
+}
+
+#[derive(Asset, TypePath, Deserialize, Debug, Clone)]
+pub struct WaveTable {
+    pub waves: Vec<WaveDefinition>,
+}
+
+impl WaveTable {
+    /// Checks the values the spawner can't cope with, naming the first bad wave.
+    pub fn validate(&self) -> Result<(), String> {
+        for (index, wave) in self.waves.iter().enumerate() {
+            wave.spawn_ring
+                .validate()
+                .map_err(|error| format!("wave {} ({}): {}", index, wave.archetype, error))?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for WaveTable {
+    // Used when assets/default.waves.ron is missing or fails to parse.
+    fn default() -> Self {
+        let spawn_ring = SpawnRing {
+            inner: 1.0,
+            outer: 1.2,
+            height: 1000.,
+        };
+        WaveTable {
+            waves: vec![
+                WaveDefinition {
+                    trigger_secs: 10.,
+                    archetype: "asteroid_small".into(),
+                    count: 40,
+                    spawn_ring,
+                    impulse: WaveImpulse {
+                        linear: 0.02,
+                        torque: 0.02,
+                    },
+                },
+                WaveDefinition {
+                    trigger_secs: 60.,
+                    archetype: "asteroid_large".into(),
+                    count: 60,
+                    spawn_ring,
+                    impulse: WaveImpulse {
+                        linear: 0.02,
+                        torque: 0.07,
+                    },
+                },
+                WaveDefinition {
+                    trigger_secs: 90.,
+                    archetype: "asteroid_medium".into(),
+                    count: 80,
+                    spawn_ring,
+                    impulse: WaveImpulse {
+                        linear: 0.02,
+                        torque: 0.07,
+                    },
+                },
+                WaveDefinition {
+                    trigger_secs: 120.,
+                    archetype: "asteroid_armored".into(),
+                    count: 20,
+                    spawn_ring,
+                    impulse: WaveImpulse {
+                        linear: 0.02,
+                        torque: 0.05,
+                    },
+                },
+                WaveDefinition {
+                    trigger_secs: 150.,
+                    archetype: "asteroid_shielded".into(),
+                    count: 10,
+                    spawn_ring,
+                    impulse: WaveImpulse {
+                        linear: 0.02,
+                        torque: 0.05,
+                    },
+                },
+            ],
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct WaveTableLoader;
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum WaveTableLoaderError {
+    #[error("Could not load wave table: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse wave table: {0}")]
+    RonSpannedError(#[from] ron::error::SpannedError),
+    #[error("Invalid wave table: {0}")]
+    Invalid(String),
+}
+
+impl AssetLoader for WaveTableLoader {
+    type Asset = WaveTable;
+    type Settings = ();
+    type Error = WaveTableLoaderError;
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let wave_table = ron::de::from_bytes::<WaveTable>(&bytes)?;
+            wave_table
+                .validate()
+                .map_err(WaveTableLoaderError::Invalid)?;
+            Ok(wave_table)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["waves.ron"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_waves_parse_and_validate() {
+        let wave_table: WaveTable = ron::de::from_str(include_str!("../assets/default.waves.ron"))
+            .expect("assets/default.waves.ron parses");
+        assert!(!wave_table.waves.is_empty());
+        assert_eq!(wave_table.validate(), Ok(()));
+    }
+
+    #[test]
+    fn inverted_spawn_ring_is_rejected() {
+        let mut wave_table = WaveTable::default();
+        wave_table.waves[1].spawn_ring.inner = 1.5;
+        let error = wave_table.validate().unwrap_err();
+        assert!(error.starts_with("wave 1 (asteroid_large)"), "{}", error);
+    }
+
+    #[test]
+    fn negative_spawn_height_is_rejected() {
+        let mut wave_table = WaveTable::default();
+        wave_table.waves[0].spawn_ring.height = -1.;
+        assert!(wave_table.validate().is_err());
+    }
+}